@@ -8,9 +8,10 @@ if exists("b:current_syntax")
 endif
 
 
-syn keyword pncBuiltin add alias sub mul div mod swap dup print stdin map fold repeat pop def roll3 len sum
+syn keyword pncBuiltin add alias sub mul div mod swap dup print stdin map fold repeat pop def roll3 len sum try throw assert assert-eq save load fromjson tojson dsv csv tsv range iterate take takewhile lines collect nth slice reverse sort sortby uniq zip concat flatten push pop-back first last drop find indexof any all count group explode pack transpose matmul det inv solve identity dot cross norm integrate deriv root minimize polyval polyadd polymul polyder polyint polyroots polyfit npv irr pmt fv pv nper rate amortize now today strftime weekday workdays duration seconds seed rand randint shuffle sample choice normal exponential poisson deg rad grad atan2 sinh cosh tanh asinh acosh atanh std fix sci eng grouping showlen format
+syn keyword pncConstant undef true false
 
-syn match pncNumber '\(^\|\s\)\zs[+-]\?\d\+\(\.\d\+\)\?'
+syn match pncNumber '\(^\|\s\)\zs[+-]\?\d\+\(\.\d\+\|/\d\+\)\?'
 
 syn match pncQuote '\(^\|\s\),'
 
@@ -20,6 +21,7 @@ syn match pncPunct '\({\|}\|\[\[\|\]\]\|\[\|\]\)'
 
 hi def link pncBuiltin Keyword
 hi def link pncNumber  Number
+hi def link pncConstant Constant
 hi def link pncQuote   Special
 hi def link pncComment Comment
 hi def link pncPunct   PreProc
@@ -1,27 +1,70 @@
 use std::cmp::Ordering;
 
-use num::{Zero, ToPrimitive};
+use num::{BigInt, One, Signed, Zero, ToPrimitive};
 use num::bigint::ToBigInt;
 
+use seq::Seq;
 use words::Value;
-use calc::Calc;
+use calc::{float_cast, AngleMode, Calc};
+use csv;
+use datetime;
 use errors::*;
+use json;
 
 
 impl Calc {
     pub fn builtin_div(&mut self) -> Result<()> {
-        let y = self.get_float_cast()?;
-        let x = self.get_float_cast()?;
-        if y == 0.0 {
-            return Err(ErrorKind::DivisionByZero.into());
-        }
-        self.data.push(Value::Float(x / y));
+        self.perform_broadcast(|x, y| {
+            if datetime::is_time(&x) || datetime::is_time(&y) {
+                return datetime::div(x, y);
+            }
+            let exact = match (&x, &y) {
+                (&Value::Rational(_), _) | (_, &Value::Rational(_)) => true,
+                _ => false,
+            };
+            if let (true, Some(x), Some(y)) = (exact, x.as_rational(), y.as_rational()) {
+                if y.is_zero() {
+                    return Err(ErrorKind::DivisionByZero.into());
+                }
+                return Ok(Value::from_rational(x / y));
+            }
+            let y = float_cast(y)?;
+            let x = float_cast(x)?;
+            if y == 0.0 {
+                return Err(ErrorKind::DivisionByZero.into());
+            }
+            Ok(Value::Float(x / y))
+        })
+    }
+
+    pub fn builtin_atan2(&mut self) -> Result<()> {
+        let mode = self.angle_mode();
+        self.perform_float_binary(|y, x| mode.from_radians(y.atan2(x)))
+    }
+
+    pub fn builtin_deg(&mut self) -> Result<()> {
+        self.set_angle_mode(AngleMode::Degrees);
+        Ok(())
+    }
+
+    pub fn builtin_rad(&mut self) -> Result<()> {
+        self.set_angle_mode(AngleMode::Radians);
+        Ok(())
+    }
+
+    pub fn builtin_grad(&mut self) -> Result<()> {
+        self.set_angle_mode(AngleMode::Gradians);
         Ok(())
     }
 
     pub fn builtin_print(&mut self) -> Result<()> {
         let val = self.get_operand()?;
-        println!("{}", val);
+        println!("{}", self.number_format.value(&self.collect_finite(val)?));
+        Ok(())
+    }
+
+    pub fn builtin_pop(&mut self) -> Result<()> {
+        self.data.pop();
         Ok(())
     }
 
@@ -41,6 +84,7 @@ impl Calc {
             let line = line.unwrap();
             if let Some(val) = Value::parse(&line) {
                 vec.push(val);
+                self.check_vector_len(vec.len())?;
             }
         }
         self.data.push(Value::Vector(vec));
@@ -48,32 +92,52 @@ impl Calc {
     }
 
 
+    pub fn builtin_lines(&mut self) -> Result<()> {
+        self.data.push(Value::Seq(Seq::Lines));
+        Ok(())
+    }
+
+    pub fn builtin_dsv(&mut self) -> Result<()> {
+        let header = self.get_int()?;
+        let delim = self.get_word()?;
+        let delim = csv::delimiter(&delim)
+            .ok_or_else::<Error, _>(|| ErrorKind::WrongTypeOperand(Value::QuotedWord(delim.clone()), "single character").into())?;
+        let stdin = ::std::io::stdin();
+        let (table, warnings) = csv::read_table(stdin.lock(), delim, !header.is_zero())?;
+        if let Value::Vector(ref rows) = table {
+            self.check_vector_len(rows.len())?;
+        }
+        for warning in warnings {
+            self.debugger.borrow_mut().say(&format!("Warning: {}", warning));
+        }
+        self.data.push(table);
+        Ok(())
+    }
+
     pub fn builtin_map(&mut self) -> Result<()> {
         let block = self.get_block()?;
-        let vec = self.get_vector()?;
-        let mut result = Vec::with_capacity(vec.len());
-        for val in vec {
-            let mut sub_calc = self.sub_calc();
-            sub_calc.data.push(val);
-            sub_calc.run(&block)?;
-            if let Some(res) = sub_calc.data.pop() {
-                result.push(res);
-            } else {
-                return Err(ErrorKind::BlockNoResult.into());
+        match self.get_operand()? {
+            Value::Vector(vec) => {
+                let mut result = Vec::with_capacity(vec.len());
+                for val in vec {
+                    result.push(self.apply_block(&block, vec![val])?);
+                }
+                self.data.push(Value::Vector(result));
             }
+            Value::Seq(seq) => self.data.push(Value::Seq(Seq::Map(Box::new(seq), block))),
+            v => return Err(ErrorKind::WrongTypeOperand(v, "vector or seq").into()),
         }
-        self.data.push(Value::Vector(result));
         Ok(())
     }
 
     pub fn builtin_fold(&mut self) -> Result<()> {
         let block = self.get_block()?;
         let init = self.get_operand()?;
-        let values = self.get_vector()?;
+        let mut values = self.get_seq()?;
 
         let mut sub_calc = self.sub_calc();
         sub_calc.data.push(init);
-        for val in values {
+        while let Some(val) = values.next(self)? {
             sub_calc.data.push(val);
             sub_calc.run(&block)?;
         }
@@ -87,12 +151,12 @@ impl Calc {
 
     pub fn builtin_fold1(&mut self) -> Result<()> {
         let block = self.get_block()?;
-        let mut values = self.get_vector()?.into_iter();
+        let mut values = self.get_seq()?;
 
-        if let Some(init) = values.next() {
+        if let Some(init) = values.next(self)? {
             let mut sub_calc = self.sub_calc();
             sub_calc.data.push(init);
-            for val in values {
+            while let Some(val) = values.next(self)? {
                 sub_calc.data.push(val);
                 sub_calc.run(&block)?;
             }
@@ -104,40 +168,95 @@ impl Calc {
 
     pub fn builtin_filter(&mut self) -> Result<()> {
         let block = self.get_block()?;
-        let values = self.get_vector()?;
-        let mut result = Vec::with_capacity(values.len());
-        for val in values {
-            let mut sub_calc = self.sub_calc();
-            sub_calc.data.push(val.clone());
-            sub_calc.run(&block)?;
-            if let Some(res) = sub_calc.data.pop() {
-                match res {
-                    Value::Int(ref x) if !x.is_zero() => { result.push(val); }
-                    Value::Bool(true) => { result.push(val); }
-                    _ => {}
+        match self.get_operand()? {
+            Value::Vector(values) => {
+                let mut result = Vec::with_capacity(values.len());
+                for val in values {
+                    if self.apply_block(&block, vec![val.clone()])?.is_true() {
+                        result.push(val);
+                    }
                 }
-            } else {
-                return Err(ErrorKind::BlockNoResult.into());
+                result.shrink_to_fit();
+                self.data.push(Value::Vector(result));
             }
+            Value::Seq(seq) => self.data.push(Value::Seq(Seq::Filter(Box::new(seq), block))),
+            v => return Err(ErrorKind::WrongTypeOperand(v, "vector or seq").into()),
         }
-        result.shrink_to_fit();
-        self.data.push(Value::Vector(result));
         Ok(())
     }
 
     pub fn builtin_length(&mut self) -> Result<()> {
-        let len = {
-            let a = self.get_operand()?;
-            if let Value::Vector(ref vec) = a {
-                vec.len()
-            } else {
-                return Err(ErrorKind::WrongTypeOperand(a, "vector").into());
+        let len = match self.get_operand()? {
+            Value::Vector(vec) => vec.len(),
+            Value::Seq(mut seq) => {
+                if !seq.is_finite() {
+                    return Err(ErrorKind::WrongTypeOperand(Value::Seq(seq), "vector or finite seq").into());
+                }
+                let mut len = 0;
+                while seq.next(self)?.is_some() {
+                    len += 1;
+                }
+                len
             }
+            v => return Err(ErrorKind::WrongTypeOperand(v, "vector or seq").into()),
         };
         self.data.push(Value::Int(len.to_bigint().unwrap()));
         Ok(())
     }
 
+    pub fn builtin_range(&mut self) -> Result<()> {
+        let to = self.get_int()?;
+        let from = self.get_int()?;
+        self.data.push(Value::Seq(Seq::range(from, to)));
+        Ok(())
+    }
+
+    pub fn builtin_iterate(&mut self) -> Result<()> {
+        let block = self.get_block()?;
+        let value = self.get_operand()?;
+        self.data.push(Value::Seq(Seq::iterate(value, block)));
+        Ok(())
+    }
+
+    pub fn builtin_take(&mut self) -> Result<()> {
+        let n = self.get_int()?.to_usize()
+            .ok_or::<Error>(ErrorKind::BigIntTooLarge.into())?;
+        match self.get_operand()? {
+            Value::Vector(mut vec) => {
+                vec.truncate(n);
+                self.data.push(Value::Vector(vec));
+            }
+            Value::Seq(seq) => self.data.push(Value::Seq(Seq::Take(Box::new(seq), n))),
+            v => return Err(ErrorKind::WrongTypeOperand(v, "vector or seq").into()),
+        }
+        Ok(())
+    }
+
+    pub fn builtin_takewhile(&mut self) -> Result<()> {
+        let block = self.get_block()?;
+        match self.get_operand()? {
+            Value::Vector(vec) => {
+                let mut result = Vec::new();
+                for val in vec {
+                    if !self.apply_block(&block, vec![val.clone()])?.is_true() {
+                        break;
+                    }
+                    result.push(val);
+                }
+                self.data.push(Value::Vector(result));
+            }
+            Value::Seq(seq) => self.data.push(Value::Seq(Seq::TakeWhile(Box::new(seq), block))),
+            v => return Err(ErrorKind::WrongTypeOperand(v, "vector or seq").into()),
+        }
+        Ok(())
+    }
+
+    pub fn builtin_collect(&mut self) -> Result<()> {
+        let vec = self.get_seq()?.collect(self)?;
+        self.data.push(Value::Vector(vec));
+        Ok(())
+    }
+
     pub fn builtin_swap(&mut self) -> Result<()> {
         let a = self.get_operand()?;
         let b = self.get_operand()?;
@@ -159,6 +278,7 @@ impl Calc {
         let n = self.get_int_cast()?;
         let block = self.get_block()?;
         for _ in 0..n {
+            self.tick()?;
             self.run(&block)?;
         }
         Ok(())
@@ -214,6 +334,8 @@ impl Calc {
             (Value::Bool(x), Value::Bool(y)) => y.cmp(&x),
             (Value::Int(x), Value::Int(y)) => y.cmp(&x),
             (Value::QuotedWord(x), Value::QuotedWord(y)) => y.cmp(&x),
+            (Value::DateTime(x), Value::DateTime(y)) => y.cmp(&x),
+            (Value::Duration(x), Value::Duration(y)) => y.cmp(&x),
             (Value::Float(x), Value::Float(y)) => {
                 match y.partial_cmp(&x) {
                     Some(ord) => ord,
@@ -223,9 +345,17 @@ impl Calc {
                     }
                 }
             }
-            (x, y) => {
-                return Err(format!("cannot compare '{}' (of type {}) with '{}' (of type {})", x, x.type_of(), y, y.type_of()).into());
-            }
+            (x, y) => match (x.as_rational(), y.as_rational(), x.as_float_cast(), y.as_float_cast()) {
+                (Some(x), Some(y), _, _) => y.cmp(&x),
+                (_, _, Some(x), Some(y)) => match y.partial_cmp(&x) {
+                    Some(ord) => ord,
+                    None => {
+                        self.data.push(Value::Undef);
+                        return Ok(());
+                    }
+                },
+                _ => return Err(format!("cannot compare '{}' (of type {}) with '{}' (of type {})", x, x.type_of(), y, y.type_of()).into()),
+            },
         };
         let cmp = match ord {
             Ordering::Less => -1,
@@ -236,6 +366,34 @@ impl Calc {
         Ok(())
     }
 
+    pub fn builtin_sign(&mut self) -> Result<()> {
+        self.perform_broadcast_unary(|x| {
+            let sign = match x {
+                Value::Undef => return Ok(Value::Undef),
+                Value::Int(ref i) => i.signum(),
+                Value::Rational(ref r) => r.numer().signum(),
+                Value::Float(f) if f.is_nan() => return Ok(Value::Undef),
+                Value::Float(f) if f > 0.0 => BigInt::one(),
+                Value::Float(f) if f < 0.0 => -BigInt::one(),
+                Value::Float(_) => BigInt::zero(),
+                x => return Err(ErrorKind::WrongTypeOperand(x, "number").into()),
+            };
+            Ok(Value::Int(sign))
+        })
+    }
+
+    pub fn builtin_abs(&mut self) -> Result<()> {
+        self.perform_broadcast_unary(|x| {
+            Ok(match x {
+                Value::Undef => Value::Undef,
+                Value::Int(i) => Value::Int(i.abs()),
+                Value::Rational(r) => Value::Rational(r.abs()),
+                Value::Float(f) => Value::Float(f.abs()),
+                x => return Err(ErrorKind::WrongTypeOperand(x, "number").into()),
+            })
+        })
+    }
+
     pub fn buildin_if(&mut self) -> Result<()> {
         let else_block = self.get_operand()?;
         let then_block = self.get_operand()?;
@@ -259,30 +417,264 @@ impl Calc {
     }
 
     pub fn builtin_mod(&mut self) -> Result<()> {
-        let b = self.get_int()?;
-        let a = self.get_int()?;
-        self.data.push(Value::Int(a % b));
-        Ok(())
+        self.perform_broadcast(|a, b| {
+            match (a, b) {
+                (Value::Int(_), Value::Int(ref b)) if b.is_zero() => Err(ErrorKind::DivisionByZero.into()),
+                (Value::Int(a), Value::Int(b)) => Ok(Value::Int(a % b)),
+                (Value::Int(_), v) | (v, _) => Err(ErrorKind::WrongTypeOperand(v, "int").into()),
+            }
+        })
     }
 
     pub fn builtin_pow(&mut self) -> Result<()> {
-        let n = self.get_operand()?;
-        let a = self.get_float_cast()?;
-        match n {
-            Value::Int(bignum) => {
-                let m = bignum.to_i32()
-                    .ok_or::<Error>(ErrorKind::BigIntTooLarge.into())?;
-                self.data.push(Value::Float(a.powi(m)));
+        self.perform_broadcast(|a, n| {
+            let a = float_cast(a)?;
+            match n {
+                Value::Int(bignum) => {
+                    let m = bignum.to_i32()
+                        .ok_or::<Error>(ErrorKind::BigIntTooLarge.into())?;
+                    Ok(Value::Float(a.powi(m)))
+                }
+                Value::Float(b) => Ok(Value::Float(a.powf(b))),
+                n @ Value::Rational(_) => Ok(Value::Float(a.powf(float_cast(n)?))),
+                v => Err(ErrorKind::WrongTypeOperand(v, "number").into()),
+            }
+        })
+    }
+
+    pub fn builtin_try(&mut self) -> Result<()> {
+        let handler = self.get_operand()?;
+        let body = self.get_operand()?;
+        let saved = self.data.clone();
+        let result = match body {
+            Value::Block(block) => self.run(block),
+            v => {
+                self.data.push(v);
                 Ok(())
             }
-            Value::Float(b) => {
-                self.data.push(Value::Float(a.powf(b)));
+        };
+        if let Err(err) = result {
+            // Running out of resources or being interrupted is not an error
+            // of the body
+            match *err.root().kind() {
+                ErrorKind::LimitExceeded(..) | ErrorKind::Interrupted => return Err(err),
+                _ => {}
+            }
+            self.data = saved;
+            let root = err.root();
+            let error = match *root.kind() {
+                ErrorKind::Thrown(ref v @ Value::Error(..)) => v.clone(),
+                ErrorKind::Thrown(ref v) => Value::Error("thrown".to_owned(), Box::new(v.clone())),
+                ref kind => Value::Error(kind.name().to_owned(), Box::new(Value::QuotedWord(root.to_string()))),
+            };
+            self.data.push(error);
+            match handler {
+                Value::Block(block) => {
+                    self.run(block)
+                        .chain_err(|| "error while evaluating handler of try command")?;
+                }
+                v => self.data.push(v),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn builtin_throw(&mut self) -> Result<()> {
+        let val = self.get_operand()?;
+        Err(ErrorKind::Thrown(val).into())
+    }
+
+    pub fn builtin_fromjson(&mut self) -> Result<()> {
+        let text = self.get_word()?;
+        let value = json::parse(&text)?;
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_tojson(&mut self) -> Result<()> {
+        let value = self.get_operand()?;
+        let value = self.collect_finite(value)?;
+        self.data.push(Value::QuotedWord(json::format(&value)));
+        Ok(())
+    }
+
+    pub fn builtin_error(&mut self) -> Result<()> {
+        let kind = self.get_word()?;
+        let value = self.get_operand()?;
+        self.data.push(Value::Error(kind, Box::new(value)));
+        Ok(())
+    }
+
+    pub fn builtin_errkind(&mut self) -> Result<()> {
+        match self.get_operand()? {
+            Value::Error(kind, _) => {
+                self.data.push(Value::QuotedWord(kind));
                 Ok(())
             }
-            v => {
-                Err(ErrorKind::WrongTypeOperand(v, "int or float").into())
+            v => Err(ErrorKind::WrongTypeOperand(v, "error").into()),
+        }
+    }
+
+    pub fn builtin_errval(&mut self) -> Result<()> {
+        match self.get_operand()? {
+            Value::Error(_, value) => {
+                self.data.push(*value);
+                Ok(())
             }
+            v => Err(ErrorKind::WrongTypeOperand(v, "error").into()),
+        }
+    }
+
+    pub fn builtin_assert(&mut self) -> Result<()> {
+        let val = self.get_operand()?;
+        if val.is_true() {
+            Ok(())
+        } else {
+            Err(ErrorKind::AssertionFailed(format!("expected a true value, got '{}'", val)).into())
+        }
+    }
+
+    pub fn builtin_assert_eq(&mut self) -> Result<()> {
+        let expected = self.get_operand()?;
+        let actual = self.get_operand()?;
+        let equal = match (&actual, &expected) {
+            (&Value::Int(ref x), &Value::Float(y)) | (&Value::Float(y), &Value::Int(ref x)) => {
+                x.to_f64() == Some(y)
+            }
+            (x, y) => x == y,
+        };
+        if equal {
+            Ok(())
+        } else {
+            Err(ErrorKind::AssertionFailed(format!("expected '{}' but got '{}'", expected, actual)).into())
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use num::bigint::ToBigInt;
+    use words::Value;
+    use calc::Calc;
+    use limits::Limits;
+    use testing::eval_float;
+
+    fn int(i: i64) -> Value {
+        Value::Int(i.to_bigint().unwrap())
+    }
+
+    #[test]
+    fn test_angle_mode() {
+        let cases = [
+            ("90 sin", (90f64).sin()),
+            ("deg 90 sin", 1.0),
+            ("deg 60 cos", 0.5),
+            ("grad 50 tan", 1.0),
+            ("deg 1 asin", 90.0),
+            ("grad -1 0 atan2", -100.0),
+            ("deg rad 1 atan", ::std::f64::consts::FRAC_PI_4),
+            ("deg [ 30 ] { sin } map explode", 0.5),
+            ("1 sinh asinh", 1.0),
+            ("2 cosh acosh", 2.0),
+            ("0.5 tanh atanh", 0.5),
+        ];
+        for &(source, expected) in &cases {
+            let x = eval_float(source);
+            assert!((x - expected).abs() < 1e-12, "{}: {} != {}", source, x, expected);
+        }
+    }
+
+    #[test]
+    fn test_broadcast() {
+        let eval = |source: &str| {
+            let mut calc = Calc::with_prelude().unwrap();
+            calc.eval(source).map(|_| calc.data)
+        };
+        let cases = [
+            ("[ 1 2 3 ] 2 .", "[ 2 4 6 ]"),
+            ("10 [ 1 2 ] -", "[ 9 8 ]"),
+            ("[ 1 2 ] [ 10 20 ] +", "[ 11 22 ]"),
+            ("[ [ 1 2 ] 3 ] [ 1 2 ] .", "[ [ 1 2 ] 6 ]"),
+            ("[ 7 8 ] 3 %", "[ 1 2 ]"),
+            ("[ 1 2 ] 2 ^", "[ 1.0 4.0 ]"),
+            ("4 1/2 ^ [ 8 27 ] 1/3 ^", "2.0 [ 2.0 3.0 ]"),
+            ("[ 1 4 ] sqrt", "[ 1.0 2.0 ]"),
+            ("[ 2 4 ] 2 /", "[ 1.0 2.0 ]"),
+            ("5 upto 2 .", "[ 2 4 6 8 10 ]"),
+            ("3 upto 3 upto { 10 . } map +", "[ 11 22 33 ]"),
+            ("[ -1 2 ] abs [ -1/2 0 2.5 ] sign", "[ 1 2 ] [ -1 0 1 ]"),
+            ("-3/2 abs -2.5 abs 3 upto -1 . sign", "3/2 2.5 [ -1 -1 -1 ]"),
+        ];
+        for &(source, expected) in &cases {
+            assert_eq!(eval(source).unwrap(), eval(expected).unwrap(), "{}", source);
+        }
+        let err = eval("[ 1 2 ] [ 1 2 3 ] +").unwrap_err();
+        assert_eq!(err.root().kind().name(), "length-mismatch");
+        assert!(eval("[ 1 2 ] [ 1 0 ] /").is_err());
+        assert!(eval("1 { 1 + } iterate 2 .").is_err());
+    }
+
+    #[test]
+    fn test_try() {
+        let mut calc = Calc::new();
+        calc.run("1 2 { 3 pop pop pop add } { errkind } try".split_whitespace()).unwrap();
+        assert_eq!(calc.data, vec![int(1), int(2), Value::QuotedWord("missing-operand".to_owned())]);
+
+        let mut calc = Calc::new();
+        calc.run("{ 1 2 } { 0 } try".split_whitespace()).unwrap();
+        assert_eq!(calc.data, vec![int(1), int(2)]);
+    }
+
+    #[test]
+    fn test_try_uncaught() {
+        let mut calc = Calc::new();
+        calc.set_limits(Limits { instructions: Some(10_000), ..Limits::default() });
+        let err = calc.eval("{ { 1 pop } 100000 repeat } { pop } try").unwrap_err();
+        assert_eq!(err.root().kind().name(), "limit-exceeded");
+
+        let mut calc = Calc::new();
+        let interrupt = calc.interrupt_handle();
+        calc.register("interrupt", "( -- )", move |_| {
+            interrupt.request();
+            Ok(())
+        });
+        let err = calc.eval("{ { interrupt 1 } { pop } try } 10 repeat").unwrap_err();
+        assert_eq!(err.root().kind().name(), "interrupted");
+        // Taking the request does not make a second one look like the first
+        assert!(calc.interrupt_handle().request());
+        calc.interrupt_handle().clear();
+        assert!(!calc.interrupt_handle().request());
+    }
+
+    #[test]
+    fn test_throw() {
+        let mut calc = Calc::new();
+        calc.run("{ 1 42 throw } { errval } try".split_whitespace()).unwrap();
+        assert_eq!(calc.data, vec![int(42)]);
+
+        let mut calc = Calc::new();
+        calc.run("{ { 1 0 div } { throw } try } { errkind } try".split_whitespace()).unwrap();
+        assert_eq!(calc.data, vec![Value::QuotedWord("division-by-zero".to_owned())]);
+
+        let mut calc = Calc::new();
+        assert!(calc.run("7 throw".split_whitespace()).is_err());
+    }
+
+    #[test]
+    fn test_cmp() {
+        let mut calc = Calc::with_prelude().unwrap();
+        calc.eval("1/2 0.5 eq 1/3 0.5 cmp 0.5 1/3 cmp").unwrap();
+        assert_eq!(calc.data, vec![int(1), int(-1), int(1)]);
+        assert!(calc.eval("1/2 ,x cmp").is_err());
+    }
+
+    #[test]
+    fn test_assert() {
+        let mut calc = Calc::new();
+        assert!(calc.run("1 assert 2 2 assert-eq 3 3.0 assert-eq".split_whitespace()).is_ok());
+        assert!(calc.run("1/2 assert 1.0 assert".split_whitespace()).is_ok());
+        assert!(calc.run("0 assert".split_whitespace()).is_err());
+        assert!(calc.run("0.0 assert".split_whitespace()).is_err());
+        assert!(calc.run("1 2 assert-eq".split_whitespace()).is_err());
+    }
+}
@@ -1,7 +1,89 @@
+use std::cell::{Cell, RefCell, RefMut};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use num::{BigInt, ToPrimitive};
+use num::rational::BigRational;
+use shlex;
+
 use errors::*;
-use words::{BuiltinWord, Operation, Value, Word};
+use words::{Native, Operation, Value, Word};
 use dict;
+use format::NumberFormat;
+use check;
+use datetime;
+use debug::{self, Debugger, Frame};
+use json;
+use limits::Limits;
+use rng::Rng;
+use seq::Seq;
+use session;
+
+/// Requests to stop an evaluation, e.g. from a Ctrl-C handler.
+#[derive(Default)]
+pub struct Interrupt {
+    /// Set by `request` and taken by the next word executed.
+    requested: AtomicBool,
+    /// Set by `request` until `clear`, so a repeated request can be told
+    /// from the first one.
+    pending: AtomicBool,
+}
+
+impl Interrupt {
+    /// Ask the running evaluation to stop at the next word. Returns whether
+    /// an earlier request was made since the last `clear`.
+    pub fn request(&self) -> bool {
+        self.requested.store(true, Ordering::SeqCst);
+        self.pending.swap(true, Ordering::SeqCst)
+    }
+
+    /// Forget the requests made so far.
+    pub fn clear(&self) {
+        self.requested.store(false, Ordering::SeqCst);
+        self.pending.store(false, Ordering::SeqCst);
+    }
+
+    fn take(&self) -> bool {
+        self.requested.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// The unit of angles taken by trigonometric words and returned by their
+/// inverses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AngleMode {
+    Radians,
+    Degrees,
+    Gradians,
+}
+
+impl AngleMode {
+    pub fn to_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_radians(),
+            AngleMode::Gradians => angle * (::std::f64::consts::PI / 200.0),
+        }
+    }
+
+    pub fn from_radians(self, angle: f64) -> f64 {
+        match self {
+            AngleMode::Radians => angle,
+            AngleMode::Degrees => angle.to_degrees(),
+            AngleMode::Gradians => angle * (200.0 / ::std::f64::consts::PI),
+        }
+    }
+}
+
+impl Default for AngleMode {
+    fn default() -> AngleMode {
+        AngleMode::Radians
+    }
+}
 
 enum CalcState {
     Reading {
@@ -17,6 +99,13 @@ pub struct Calc {
     dict: dict::Dictionary,
     pub data: Vec<Value>,
     state: Vec<CalcState>,
+    pub debugger: Rc<RefCell<Debugger>>,
+    limits: Limits,
+    executed: Rc<Cell<u64>>,
+    interrupt: Arc<Interrupt>,
+    rng: Rc<RefCell<Rng>>,
+    angle_mode: AngleMode,
+    pub number_format: NumberFormat,
 }
 
 #[allow(new_without_default)]
@@ -26,34 +115,217 @@ impl Calc {
             dict: Default::default(),
             data: Vec::new(),
             state: Vec::new(),
+            debugger: Default::default(),
+            limits: Default::default(),
+            executed: Default::default(),
+            interrupt: Default::default(),
+            rng: Default::default(),
+            angle_mode: Default::default(),
+            number_format: Default::default(),
         }
     }
 
+    /// Create a calculator with the builtin prelude loaded.
+    pub fn with_prelude() -> Result<Calc> {
+        let mut calc = Calc::new();
+        calc.eval(::PRELUDE).chain_err(|| "could not execute builtin prelude")?;
+        calc.new_scope();
+        Ok(calc)
+    }
+
+    /// Put further definitions in a new dictionary on top of the current
+    /// one. Only the words defined in the top dictionary are saved in a session.
+    pub fn new_scope(&mut self) {
+        self.dict = dict::Dictionary::with_parent(&self.dict);
+    }
+
+    /// A script restoring the stack and the words defined in the current scope.
+    pub fn session(&self) -> String {
+        session::dump(&self.dict, &self.data)
+    }
+
+    pub fn save_session<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut file = File::create(path)
+            .chain_err(|| format!("could not create session file {:?}", path))?;
+        file.write_all(self.session().as_bytes())
+            .chain_err(|| format!("could not write session file {:?}", path))
+    }
+
+    /// Evaluate the file at `path`, e.g. a session written by `save_session`.
+    pub fn load_session<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut source = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .chain_err(|| format!("could not read file {:?}", path))?;
+        self.eval(&source).chain_err(|| format!("failed to execute file {:?}", path))
+    }
+
     pub fn sub_calc(&self) -> Calc {
         Calc {
             dict: dict::Dictionary::with_parent(&self.dict),
             data: Vec::new(),
             state: Vec::new(),
+            debugger: Rc::clone(&self.debugger),
+            limits: self.limits,
+            executed: Rc::clone(&self.executed),
+            interrupt: Arc::clone(&self.interrupt),
+            rng: Rc::clone(&self.rng),
+            angle_mode: self.angle_mode,
+            number_format: self.number_format,
         }
     }
 
     pub fn list_available_words(&self) {
         for (word, aliases) in self.dict.available_words() {
-            if aliases.is_empty() {
-                println!("{}", word);
-            } else {
-                println!("{} (aliases: {})", word, aliases.join(", "));
+            let signature = self.dict.lookup_doc(&word).and_then(|doc| doc.signature);
+            let mut line = word.clone();
+            if let Some(signature) = signature {
+                line = format!("{} {}", line, signature);
+            }
+            if !aliases.is_empty() {
+                line = format!("{} (aliases: {})", line, aliases.join(", "));
+            }
+            println!("{}", line);
+        }
+    }
+
+    pub fn print_help(&self, word: &str) -> Result<()> {
+        for line in self.help(word)? {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    /// The lines of help on `word`: its signature, doc and aliases.
+    pub fn help(&self, word: &str) -> Result<Vec<String>> {
+        let name = self.dict.resolve(word)
+            .ok_or_else::<Error, _>(|| ErrorKind::UnknownWord(word.to_owned()).into())?;
+        let doc = self.dict.lookup_doc(&name);
+        let mut lines = vec![];
+        match doc.as_ref().and_then(|doc| doc.signature.as_ref()) {
+            Some(signature) => lines.push(format!("{} {}", name, signature)),
+            None => lines.push(name.clone()),
+        }
+        match doc {
+            Some(ref doc) if !doc.text.is_empty() => lines.push(format!("    {}", doc.text)),
+            _ => lines.push("    (undocumented)".to_owned()),
+        }
+        if let Some(aliases) = self.dict.available_words().remove(&name) {
+            if !aliases.is_empty() {
+                lines.push(format!("    aliases: {}", aliases.join(", ")));
+            }
+        }
+        Ok(lines)
+    }
+
+    /// A handle to interrupt evaluations of this calculator from another
+    /// thread.
+    pub fn interrupt_handle(&self) -> Arc<Interrupt> {
+        Arc::clone(&self.interrupt)
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Restart the random number generator from `seed`, making the numbers
+    /// it generates reproducible.
+    pub fn seed(&self, seed: u64) {
+        *self.rng.borrow_mut() = Rng::with_seed(seed);
+    }
+
+    pub fn rng<'a>(&'a self) -> RefMut<'a, Rng> {
+        self.rng.borrow_mut()
+    }
+
+    pub fn angle_mode(&self) -> AngleMode {
+        self.angle_mode
+    }
+
+    pub fn set_angle_mode(&mut self, mode: AngleMode) {
+        self.angle_mode = mode;
+    }
+
+    /// Restrict further evaluation to `limits`, resetting the instruction count.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+        self.executed.set(0);
+    }
+
+    /// Count the execution of a word against the instruction limit, and
+    /// stop if an interrupt was requested.
+    pub fn tick(&self) -> Result<()> {
+        if self.interrupt.take() {
+            return Err(ErrorKind::Interrupted.into());
+        }
+        let executed = self.executed.get() + 1;
+        self.executed.set(executed);
+        match self.limits.instructions {
+            Some(max) if executed > max => Err(ErrorKind::LimitExceeded("instruction", max).into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_vector_len(&self, len: usize) -> Result<()> {
+        match self.limits.vector_length {
+            Some(max) if len > max => Err(ErrorKind::LimitExceeded("vector length", max as u64).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check the stack, and the value on top of it, against the limits.
+    fn check_limits(&self) -> Result<()> {
+        if let Some(max) = self.limits.stack_depth {
+            if self.data.len() > max {
+                return Err(ErrorKind::LimitExceeded("stack depth", max as u64).into());
             }
         }
+        match self.data.last() {
+            Some(&Value::Vector(ref vec)) => self.check_vector_len(vec.len()),
+            Some(&Value::Int(ref i)) => match self.limits.int_bits {
+                Some(max) if i.bits() > max => Err(ErrorKind::LimitExceeded("integer size", max as u64).into()),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    pub fn defined_words(&self) -> Vec<Word> {
+        self.dict.available_words().into_iter().map(|(word, _)| word).collect()
+    }
+
+    /// Statically check the stack effects of all defined words.
+    pub fn check(&self) -> Vec<check::Warning> {
+        check::Checker::new(&self.dict).check_all()
     }
 
     pub fn print_stack(&self) -> Result<()> {
         for val in &self.data {
-            println!("{}", val);
+            println!("{}", self.number_format.value(&self.collect_finite(val.clone())?));
         }
         Ok(())
     }
 
+    /// Print the stack as a JSON array, bottom first.
+    pub fn print_stack_json(&self) -> Result<()> {
+        println!("{}", json::format(&self.collect_finite(Value::Vector(self.data.clone()))?));
+        Ok(())
+    }
+
+    /// `value` with the finite sequences in it collected into vectors.
+    pub fn collect_finite(&self, value: Value) -> Result<Value> {
+        match value {
+            Value::Seq(ref seq) if seq.is_finite() => {
+                let vec = seq.clone().collect(self)?;
+                self.collect_finite(Value::Vector(vec))
+            }
+            Value::Vector(vec) => vec.into_iter().map(|v| self.collect_finite(v)).collect::<Result<_>>().map(Value::Vector),
+            value => Ok(value),
+        }
+    }
+
     pub fn run<I, T>(&mut self, iter: I) -> Result<()>
         where I: IntoIterator<Item = T>,
               T: AsRef<str>
@@ -66,6 +338,11 @@ impl Calc {
         Ok(())
     }
 
+    /// Split `source` into words, shell style, and run them.
+    pub fn eval(&mut self, source: &str) -> Result<()> {
+        self.run(shlex::Shlex::new(source))
+    }
+
     #[allow(cyclomatic_complexity)]
     pub fn run_one(&mut self, word: &str) -> Result<()> {
         match self.state.pop() {
@@ -88,11 +365,12 @@ impl Calc {
             }
             Some(CalcState::Collecting { mut calc }) => {
                 trace!("collecting {}", word);
-                if word == "]" {
+                // A "]" closing a nested vector is handled by the sub-calculator
+                if word == "]" && calc.state.is_empty() {
                     self.data.push(Value::Vector(calc.data));
                 } else {
                     if let Some(op) = self.dict.lookup(word) {
-                        if let Operation::Builtin(::words::BuiltinWord::Arg) = *op {
+                        if op.is_arg() {
                             match self.state.pop() {
                                 Some(CalcState::Collecting { calc: mut parent }) => {
                                     let val = parent.data.pop()
@@ -123,6 +401,15 @@ impl Calc {
             }
             None => {
                 trace!("executing {}", word);
+                self.tick()?;
+                if self.should_pause(word) {
+                    self.pause(word)?;
+                }
+                let before = if self.debugger.borrow().trace {
+                    Some(debug::format_stack(&self.data))
+                } else {
+                    None
+                };
                 if word.starts_with(',') {
                     self.data.push(Value::QuotedWord(word[1..].to_owned()));
                 } else if word == "{" {
@@ -135,12 +422,15 @@ impl Calc {
                     self.state.push(state);
                 } else if let Some(op) = self.dict.lookup(word) {
                     match *op {
-                        Operation::Builtin(builtin) => {
-                            self.run_builtin(builtin)?;
+                        Operation::Native(ref native) => {
+                            if native.io && self.limits.sandbox {
+                                return Err(ErrorKind::Sandboxed(native.name.clone()).into());
+                            }
+                            (native.func)(self)?;
                         }
                         Operation::Value(Value::Block(ref block)) => {
                             trace!("executing block: {:?}", block);
-                            self.run(block)?;
+                            return self.run_block(word, block, &op, before);
                         }
                         Operation::Value(ref v) => {
                             self.data.push(v.clone());
@@ -151,73 +441,181 @@ impl Calc {
                         .ok_or_else::<Error, _>(|| ErrorKind::WordParseError(word.to_owned()).into())?;
                     self.data.push(val);
                 }
+                self.check_limits()?;
+                if let Some(before) = before {
+                    self.debugger.borrow_mut().trace_word(word, &before, &debug::format_stack(&self.data));
+                }
             }
         }
         Ok(())
     }
 
-    #[allow(cyclomatic_complexity)]
-    fn run_builtin(&mut self, word: BuiltinWord) -> Result<()> {
-        use words::BuiltinWord::*;
-        match word {
-            Add => self.perform_binop(::std::ops::Add::add, ::std::ops::Add::add),
-            Sub => self.perform_binop(::std::ops::Sub::sub, ::std::ops::Sub::sub),
-            Mul => self.perform_binop(::std::ops::Mul::mul, ::std::ops::Mul::mul),
-            Div => self.builtin_div(),
-            Mod => self.builtin_mod(),
-            Sqrt => self.perform_unary(f64::sqrt),
-            Pow => self.builtin_pow(),
-            Exp => self.perform_unary(f64::exp),
-            Log => self.perform_float_binary(f64::log),
-            Ln => self.perform_unary(f64::ln),
-            Sin => self.perform_unary(f64::sin),
-            Cos => self.perform_unary(f64::cos),
-            Tan => self.perform_unary(f64::tan),
-            Asin => self.perform_unary(f64::asin),
-            Acos => self.perform_unary(f64::acos),
-            Atan => self.perform_unary(f64::atan),
-
-            Print => self.builtin_print(),
-            Dump => self.print_stack(),
-            Pop => {
-                self.data.pop();
-                Ok(())
-            }
-            Duplicate => self.builtin_duplicate(),
-            Stdin => self.builtin_stdin(),
-            Map => self.builtin_map(),
-            Fold => self.builtin_fold(),
-            Fold1 => self.builtin_fold1(),
-            Filter => self.builtin_filter(),
-            Length => self.builtin_length(),
-            Swap => self.builtin_swap(),
-            Over => self.builtin_over(),
-            Repeat => self.builtin_repeat(),
-            Roll3 => self.builtin_roll3(),
-            Def => {
-                let value = self.get_operand()?;
-                let name = self.get_word()?;
-                self.dict.insert(name, Operation::Value(value));
-                Ok(())
+    /// Whether to pause before `word`, when stepping or at a breakpoint on
+    /// the word it names.
+    fn should_pause(&self, word: &str) -> bool {
+        let debugger = self.debugger.borrow();
+        debugger.stepping || (!debugger.breakpoints.is_empty() &&
+                              self.dict.resolve(word).map_or(false, |name| debugger.breakpoints.contains(&name)))
+    }
+
+    /// Execute the block defined as `word`, keeping track of the call depth.
+    fn run_block(&mut self, word: &str, block: &[Word], op: &Rc<Operation>, before: Option<String>) -> Result<()> {
+        if let Some(ref before) = before {
+            self.debugger.borrow_mut().trace_word(word, before, "...");
+        }
+        if let Some(max) = self.limits.recursion_depth {
+            if self.debugger.borrow().depth() >= max {
+                return Err(ErrorKind::LimitExceeded("recursion depth", max as u64).into());
             }
-            Alias => {
-                let val = self.get_word()?;
-                let name = self.get_word()?;
-                if self.dict.lookup(&val).is_none() {
-                    return Err(ErrorKind::UnknownWord(val).into());
+        }
+        self.debugger.borrow_mut().frames.push(Frame {
+            word: word.to_owned(),
+            op: Rc::clone(op),
+        });
+        let result = self.run(block);
+        self.debugger.borrow_mut().frames.pop();
+        if let Some(before) = before {
+            self.debugger.borrow_mut().trace_word(word, &before, &debug::format_stack(&self.data));
+        }
+        result
+    }
+
+    /// Stop before executing `word` and let the user inspect the state.
+    pub fn pause(&mut self, word: &str) -> Result<()> {
+        {
+            let mut debugger = self.debugger.borrow_mut();
+            let mut lines = Vec::new();
+            match debugger.frames.last() {
+                Some(frame) => {
+                    lines.push(format!("paused before '{}' in '{}' (depth {})", word, frame.word, debugger.depth()));
+                    if let Operation::Value(Value::Block(ref block)) = *frame.op {
+                        lines.push(format!("  block: {}", debug::format_block(block)));
+                    }
                 }
-                self.dict.insert_alias(name, val);
-                Ok(())
+                None => lines.push(format!("paused before '{}'", word)),
             }
-            Apply => self.builtin_apply(),
-            Arg => {
-                Ok(())
+            lines.push(format!("  stack: {}", debug::format_stack(&self.data)));
+            for line in lines {
+                debugger.say(&line);
             }
-            Min => self.builtin_min(),
-            Max => self.builtin_max(),
-            Cmp => self.builtin_cmp(),
-            If => self.buildin_if(),
         }
+        loop {
+            let cmd = self.debugger.borrow_mut().read_command().chain_err(|| "failed to read debugger command")?;
+            match cmd {
+                Some(debug::Command::Step) => {
+                    self.debugger.borrow_mut().stepping = true;
+                    return Ok(());
+                }
+                Some(debug::Command::Continue) | None => {
+                    self.debugger.borrow_mut().stepping = false;
+                    return Ok(());
+                }
+                Some(debug::Command::Stack) => {
+                    self.debugger.borrow_mut().say(&debug::format_stack(&self.data));
+                }
+                Some(debug::Command::Where) => {
+                    let mut debugger = self.debugger.borrow_mut();
+                    let lines: Vec<String> = debugger.frames.iter().rev().map(|f| format!("  in '{}'", f.word)).collect();
+                    for line in lines {
+                        debugger.say(&line);
+                    }
+                }
+                Some(debug::Command::Inspect(name)) => {
+                    match self.dict.lookup(&name) {
+                        Some(op) => {
+                            for line in self.help(&name)? {
+                                self.debugger.borrow_mut().say(&line);
+                            }
+                            let definition = match *op {
+                                Operation::Native(_) => "<builtin>".to_owned(),
+                                Operation::Value(Value::Block(ref block)) => debug::format_block(block),
+                                Operation::Value(ref v) => v.to_string(),
+                            };
+                            self.debugger.borrow_mut().say(&format!("  = {}", definition));
+                        }
+                        None => self.debugger.borrow_mut().say(&format!("the word '{}' does not exist", name)),
+                    }
+                }
+                Some(debug::Command::Quit) => {
+                    self.debugger.borrow_mut().stepping = false;
+                    bail!("evaluation aborted from debugger");
+                }
+                Some(debug::Command::Help) => {
+                    self.debugger.borrow_mut().say(debug::HELP);
+                }
+            }
+        }
+    }
+
+    /// Define `name` as a word implemented by `f`. A leading stack effect in
+    /// `doc`, e.g. `( x y -- z )`, is used as the signature of the word.
+    pub fn register<F>(&mut self, name: &str, doc: &str, f: F)
+        where F: Fn(&mut Calc) -> Result<()> + 'static
+    {
+        self.dict.insert(name, Operation::Native(Native::new(name, f)));
+        self.dict.set_doc(name, dict::Doc::parse(doc));
+    }
+
+    /// Like `register`, for words doing input or output. These are disabled
+    /// in sandbox mode.
+    pub fn register_io<F>(&mut self, name: &str, doc: &str, f: F)
+        where F: Fn(&mut Calc) -> Result<()> + 'static
+    {
+        let native = Native {
+            io: true,
+            ..Native::new(name, f)
+        };
+        self.dict.insert(name, Operation::Native(native));
+        self.dict.set_doc(name, dict::Doc::parse(doc));
+    }
+
+    pub fn builtin_def(&mut self) -> Result<()> {
+        let value = self.get_operand()?;
+        let name = self.get_word()?;
+        self.dict.insert(name, Operation::Value(value));
+        Ok(())
+    }
+
+    pub fn builtin_save(&mut self) -> Result<()> {
+        let path = self.get_word()?;
+        self.save_session(path)
+    }
+
+    pub fn builtin_load(&mut self) -> Result<()> {
+        let path = self.get_word()?;
+        self.load_session(path)
+    }
+
+    pub fn builtin_alias(&mut self) -> Result<()> {
+        let val = self.get_word()?;
+        let name = self.get_word()?;
+        if self.dict.lookup(&val).is_none() {
+            return Err(ErrorKind::UnknownWord(val).into());
+        }
+        self.dict.insert_alias(name, val);
+        Ok(())
+    }
+
+    pub fn builtin_doc(&mut self) -> Result<()> {
+        let text = self.get_word()?;
+        let name = self.get_word()?;
+        if self.dict.lookup(&name).is_none() {
+            return Err(ErrorKind::UnknownWord(name).into());
+        }
+        self.dict.set_doc(name, dict::Doc::parse(&text));
+        Ok(())
+    }
+
+    pub fn builtin_help(&mut self) -> Result<()> {
+        let name = self.get_word()?;
+        self.print_help(&name)
+    }
+
+    pub fn builtin_break(&mut self) -> Result<()> {
+        let word = self.get_word()?;
+        let name = self.dict.resolve(&word).ok_or(ErrorKind::UnknownWord(word))?;
+        self.debugger.borrow_mut().breakpoints.insert(name);
+        Ok(())
     }
 
     pub fn get_operand(&mut self) -> Result<Value> {
@@ -255,59 +653,173 @@ impl Calc {
         })
     }
 
+    /// An operand with the finite sequences in it collected into vectors,
+    /// for words that take vectors or matrices.
+    pub fn get_collected(&mut self) -> Result<Value> {
+        let val = self.get_operand()?;
+        self.collect_finite(val)
+    }
+
+    /// A vector operand. A finite sequence is collected into one.
     pub fn get_vector(&mut self) -> Result<Vec<Value>> {
-        self.get_operand().and_then(|val| {
+        self.get_collected().and_then(|val| {
             val.into_vector().map_err(|v| ErrorKind::WrongTypeOperand(v, "vector").into())
         })
     }
 
+    /// A vector or sequence operand, as a sequence to iterate over.
+    pub fn get_seq(&mut self) -> Result<Seq> {
+        match self.get_operand()? {
+            Value::Vector(vec) => Ok(Seq::Items(vec, 0)),
+            Value::Seq(seq) => Ok(seq),
+            v => Err(ErrorKind::WrongTypeOperand(v, "vector or seq").into()),
+        }
+    }
+
+    /// The words inside `source` if it is a single block, read the way
+    /// blocks are read when evaluating.
+    pub fn parse_block(source: &str) -> Option<Vec<Word>> {
+        let mut words = shlex::Shlex::new(source);
+        if words.next().map_or(true, |word| word != "{") {
+            return None;
+        }
+        let mut reader = Calc::new();
+        reader.state.push(CalcState::Reading { block: Vec::new(), level: 0 });
+        for word in words {
+            if reader.state.is_empty() {
+                // Words after the block
+                return None;
+            }
+            reader.run_one(&word).ok()?;
+        }
+        match reader.data.pop() {
+            Some(Value::Block(block)) if reader.state.is_empty() => Some(block),
+            _ => None,
+        }
+    }
+
+    /// Run `block` in a sub-calculator with `args` on the stack and return
+    /// the value it leaves on top.
+    pub fn apply_block(&self, block: &[Word], args: Vec<Value>) -> Result<Value> {
+        self.tick()?;
+        let mut sub_calc = self.sub_calc();
+        sub_calc.data = args;
+        sub_calc.run(block)?;
+        sub_calc.data.pop().ok_or_else(|| ErrorKind::BlockNoResult.into())
+    }
+
     pub fn get_word(&mut self) -> Result<Word> {
         self.get_operand().and_then(|val| {
             val.into_word().map_err(|v| ErrorKind::WrongTypeOperand(v, "quoted word").into())
         })
     }
 
-    fn perform_unary<F>(&mut self, f: F) -> Result<()>
+    pub fn perform_unary<F>(&mut self, f: F) -> Result<()>
         where F: Fn(f64) -> f64
     {
-        let x = self.get_float_cast()?;
-        self.data.push(Value::Float(f(x)));
-        Ok(())
+        self.perform_broadcast_unary(|x| Ok(Value::Float(f(float_cast(x)?))))
+    }
+
+    /// Like `perform_unary`, for a function of an angle in the angle mode.
+    pub fn perform_trig<F>(&mut self, f: F) -> Result<()>
+        where F: Fn(f64) -> f64
+    {
+        let mode = self.angle_mode;
+        self.perform_unary(|x| f(mode.to_radians(x)))
     }
 
-    fn perform_float_binary<F>(&mut self, f: F) -> Result<()>
+    /// Like `perform_unary`, for a function giving an angle in the angle mode.
+    pub fn perform_inverse_trig<F>(&mut self, f: F) -> Result<()>
+        where F: Fn(f64) -> f64
+    {
+        let mode = self.angle_mode;
+        self.perform_unary(|x| mode.from_radians(f(x)))
+    }
+
+    pub fn perform_float_binary<F>(&mut self, f: F) -> Result<()>
         where F: Fn(f64, f64) -> f64
     {
-        let y = self.get_float_cast()?;
-        let x = self.get_float_cast()?;
-        self.data.push(Value::Float(f(x, y)));
-        Ok(())
+        self.perform_broadcast(|x, y| Ok(Value::Float(f(float_cast(x)?, float_cast(y)?))))
     }
 
-    fn perform_binop<F, G>(&mut self, f: F, g: G) -> Result<()>
+    /// Combine the top two numbers with `g` if both are ints, `h` if both
+    /// are exact, and `f` otherwise. Dates and durations are combined with `t`.
+    pub fn perform_binop<F, G, H, T>(&mut self, f: F, g: G, h: H, t: T) -> Result<()>
         where F: Fn(f64, f64) -> f64,
-              G: Fn(BigInt, BigInt) -> BigInt
+              G: Fn(BigInt, BigInt) -> BigInt,
+              H: Fn(BigRational, BigRational) -> BigRational,
+              T: Fn(Value, Value) -> Result<Value>
     {
-        let y = self.get_operand()?;
-        let x = self.get_operand()?;
-        match (x, y) {
-            (Value::Int(x),   Value::Int(y))   => self.data.push(Value::Int(g(x, y))),
-            (Value::Float(x), Value::Float(y)) => self.data.push(Value::Float(f(x, y))),
-            (Value::Int(x),   Value::Float(y)) => {
-                let x_f = x.to_f64().ok_or::<Error>(ErrorKind::BigIntTooLarge.into())?;
-                self.data.push(Value::Float(f(x_f, y)));
-            }
-            (Value::Float(x), Value::Int(y)) => {
-                let y_f = y.to_f64().ok_or::<Error>(ErrorKind::BigIntTooLarge.into())?;
-                self.data.push(Value::Float(f(x, y_f)));
-            }
-            (Value::Int(_), y) | (Value::Float(_), y) => {
-                return Err(ErrorKind::WrongTypeOperand(y, "int or float").into())
-            }
-            (x, _) => {
-                return Err(ErrorKind::WrongTypeOperand(x, "int or float").into())
+        self.perform_broadcast(|x, y| {
+            Ok(match (x, y) {
+                (Value::Int(x), Value::Int(y)) => Value::Int(g(x, y)),
+                (x, y) => match (x.as_rational(), y.as_rational()) {
+                    _ if datetime::is_time(&x) || datetime::is_time(&y) => t(x, y)?,
+                    (Some(x), Some(y)) => Value::from_rational(h(x, y)),
+                    _ => Value::Float(f(float_cast(x)?, float_cast(y)?)),
+                },
+            })
+        })
+    }
+
+    /// Replace the top value by `f` applied to it, element-wise if it is a vector.
+    pub fn perform_broadcast_unary<F>(&mut self, f: F) -> Result<()>
+        where F: Fn(Value) -> Result<Value>
+    {
+        let x = self.get_collected()?;
+        let result = broadcast_unary(x, &f)?;
+        self.data.push(result);
+        Ok(())
+    }
+
+    /// Replace the top two values by `f` applied to them, element-wise if
+    /// either is a vector.
+    pub fn perform_broadcast<F>(&mut self, f: F) -> Result<()>
+        where F: Fn(Value, Value) -> Result<Value>
+    {
+        let y = self.get_collected()?;
+        let x = self.get_collected()?;
+        let result = broadcast(x, y, &f)?;
+        self.data.push(result);
+        Ok(())
+    }
+}
+
+pub fn float_cast(val: Value) -> Result<f64> {
+    match val {
+        Value::Int(ref i) => i.to_f64().ok_or_else(|| ErrorKind::BigIntTooLarge.into()),
+        val => val.as_float_cast().ok_or_else(|| ErrorKind::WrongTypeOperand(val, "int or float").into()),
+    }
+}
+
+fn broadcast_unary<F>(x: Value, f: &F) -> Result<Value>
+    where F: Fn(Value) -> Result<Value>
+{
+    match x {
+        Value::Vector(xs) => xs.into_iter().map(|x| broadcast_unary(x, f)).collect::<Result<_>>().map(Value::Vector),
+        x => f(x),
+    }
+}
+
+/// Apply `f` to `x` and `y`. A vector is combined element by element with a
+/// vector of the same length, or with each of its elements paired with a
+/// scalar, recursively for nested vectors.
+fn broadcast<F>(x: Value, y: Value, f: &F) -> Result<Value>
+    where F: Fn(Value, Value) -> Result<Value>
+{
+    match (x, y) {
+        (Value::Vector(xs), Value::Vector(ys)) => {
+            if xs.len() != ys.len() {
+                return Err(ErrorKind::LengthMismatch(xs.len(), ys.len()).into());
             }
+            xs.into_iter().zip(ys).map(|(x, y)| broadcast(x, y, f)).collect::<Result<_>>().map(Value::Vector)
         }
-        Ok(())
+        (Value::Vector(xs), y) => {
+            xs.into_iter().map(|x| broadcast(x, y.clone(), f)).collect::<Result<_>>().map(Value::Vector)
+        }
+        (x, Value::Vector(ys)) => {
+            ys.into_iter().map(|y| broadcast(x.clone(), y, f)).collect::<Result<_>>().map(Value::Vector)
+        }
+        (x, y) => f(x, y),
     }
 }
@@ -0,0 +1,428 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use dict::Dictionary;
+use words::{Operation, Value, Word};
+
+/// Number of values a word or block takes from and leaves on the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Effect {
+    pub inputs: usize,
+    pub outputs: usize,
+}
+
+impl Effect {
+    pub fn new(inputs: usize, outputs: usize) -> Effect {
+        Effect {
+            inputs: inputs,
+            outputs: outputs,
+        }
+    }
+
+    /// Parse a signature like `( vec block -- vec )`.
+    ///
+    /// Returns `None` if the signature is malformed or describes a variable
+    /// effect, which is written with `...`.
+    pub fn parse(signature: &str) -> Option<Effect> {
+        let signature = signature.trim();
+        if !signature.starts_with('(') || !signature.ends_with(')') {
+            return None;
+        }
+        let mut parts = signature[1..signature.len() - 1].splitn(2, "--");
+        let before = parts.next()?;
+        let after = parts.next()?;
+        if before.contains("...") || after.contains("...") {
+            return None;
+        }
+        Some(Effect::new(before.split_whitespace().count(), after.split_whitespace().count()))
+    }
+
+    /// Change in stack depth.
+    fn net(&self) -> isize {
+        self.outputs as isize - self.inputs as isize
+    }
+}
+
+impl fmt::Display for Effect {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "( {} -- {} )", self.inputs, self.outputs)
+    }
+}
+
+/// A problem found in the definition of a word.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Warning {
+    pub word: Word,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "in '{}': {}", self.word, self.message)
+    }
+}
+
+/// What is statically known about a value on the stack.
+#[derive(Debug, Clone)]
+enum Item {
+    Block(Vec<Word>),
+    QuotedWord(Word),
+    Unknown,
+}
+
+/// Abstract stack used while inferring the effect of a block. Values taken
+/// from below the start of the block are counted as inputs.
+struct Sim {
+    stack: Vec<Item>,
+    inputs: usize,
+}
+
+impl Sim {
+    fn new() -> Sim {
+        Sim {
+            stack: Vec::new(),
+            inputs: 0,
+        }
+    }
+
+    fn pop(&mut self) -> Item {
+        self.stack.pop().unwrap_or_else(|| {
+            self.inputs += 1;
+            Item::Unknown
+        })
+    }
+
+    fn push(&mut self, item: Item) {
+        self.stack.push(item);
+    }
+
+    fn apply(&mut self, effect: Effect) {
+        for _ in 0..effect.inputs {
+            self.pop();
+        }
+        for _ in 0..effect.outputs {
+            self.push(Item::Unknown);
+        }
+    }
+
+    fn effect(&self) -> Effect {
+        Effect::new(self.inputs, self.stack.len())
+    }
+}
+
+/// Infers stack effects of defined words and reports inconsistencies.
+pub struct Checker<'a> {
+    dict: &'a Dictionary,
+    effects: HashMap<Word, Option<Effect>>,
+    in_progress: HashSet<Word>,
+    current: Word,
+    warnings: Vec<Warning>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(dict: &'a Dictionary) -> Checker<'a> {
+        Checker {
+            dict: dict,
+            effects: HashMap::new(),
+            in_progress: HashSet::new(),
+            current: Word::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Check every block defined in the dictionary.
+    pub fn check_all(mut self) -> Vec<Warning> {
+        for (word, _) in self.dict.available_words() {
+            if let Some(op) = self.dict.lookup(&word) {
+                if let Operation::Value(Value::Block(ref block)) = *op {
+                    self.check_def(&word, block);
+                }
+            }
+        }
+        let mut seen = HashSet::new();
+        self.warnings.retain(|w| seen.insert(w.clone()));
+        self.warnings
+    }
+
+    /// Check the body of the word `name` against its declared signature, if any.
+    pub fn check_def(&mut self, name: &str, block: &[Word]) {
+        let outer = ::std::mem::replace(&mut self.current, name.to_owned());
+        let inferred = self.infer_block(block);
+        let declared = self.dict.lookup_doc(name)
+            .and_then(|doc| doc.signature)
+            .map(|sig| (Effect::parse(&sig), sig));
+        if let (Some(inferred), Some((Some(declared), sig))) = (inferred, declared) {
+            if inferred.inputs > declared.inputs {
+                self.warn(format!("body underflows: declared {} but takes {} values", sig, inferred.inputs));
+            } else if inferred.net() != declared.net() {
+                self.warn(format!("declared {} but body has effect {}", sig, inferred));
+            }
+        }
+        self.current = outer;
+    }
+
+    fn warn(&mut self, message: String) {
+        self.warnings.push(Warning {
+            word: self.current.clone(),
+            message: message,
+        });
+    }
+
+    /// Infer the effect of a block, or `None` if it cannot be known statically.
+    fn infer_block(&mut self, words: &[Word]) -> Option<Effect> {
+        let mut sim = Sim::new();
+        let mut i = 0;
+        while i < words.len() {
+            let word = &words[i];
+            i += 1;
+            if word == "{" {
+                let (block, next) = collect(words, i, "{", "}");
+                i = next;
+                self.infer_block(&block);
+                sim.push(Item::Block(block));
+            } else if word == "[" {
+                let (inner, next) = collect(words, i, "[", "]");
+                i = next;
+                if let Some(effect) = self.infer_block(&inner) {
+                    if effect.inputs > 0 {
+                        self.warn(format!("vector body underflows by {} values, use arg to take values from outside",
+                                          effect.inputs));
+                    }
+                }
+                for _ in inner.iter().filter(|w| self.dict.lookup(w).map_or(false, |op| op.is_arg())) {
+                    sim.pop();
+                }
+                sim.push(Item::Unknown);
+            } else if word.starts_with(',') {
+                sim.push(Item::QuotedWord(word[1..].to_owned()));
+            } else if !self.step(&mut sim, word) {
+                return None;
+            }
+        }
+        Some(sim.effect())
+    }
+
+    /// Apply the effect of a single word. Returns false if it is unknown.
+    fn step(&mut self, sim: &mut Sim, word: &str) -> bool {
+        let name = match self.dict.resolve(word) {
+            Some(name) => name,
+            None if Value::parse(word).is_some() => {
+                sim.push(Item::Unknown);
+                return true;
+            }
+            None => {
+                self.warn(format!("unknown word '{}'", word));
+                return false;
+            }
+        };
+        match name.as_str() {
+            "dup" => {
+                let x = sim.pop();
+                sim.push(x.clone());
+                sim.push(x);
+            }
+            "swap" => {
+                let y = sim.pop();
+                let x = sim.pop();
+                sim.push(y);
+                sim.push(x);
+            }
+            "over" => {
+                let y = sim.pop();
+                let x = sim.pop();
+                sim.push(x.clone());
+                sim.push(y);
+                sim.push(x);
+            }
+            "pop" => {
+                sim.pop();
+            }
+            "roll3" => {
+                let z = sim.pop();
+                let y = sim.pop();
+                let x = sim.pop();
+                sim.push(y);
+                sim.push(x);
+                sim.push(z);
+            }
+            "apply" => {
+                return match sim.pop() {
+                    Item::Block(block) => match self.infer_block(&block) {
+                        Some(effect) => {
+                            sim.apply(effect);
+                            true
+                        }
+                        None => false,
+                    },
+                    Item::QuotedWord(word) => self.step(sim, &word),
+                    Item::Unknown => false,
+                };
+            }
+            "if" => {
+                let else_part = sim.pop();
+                let then_part = sim.pop();
+                sim.pop();
+                let (then_effect, else_effect) = match (self.item_effect(&then_part), self.item_effect(&else_part)) {
+                    (Some(t), Some(e)) => (t, e),
+                    _ => return false,
+                };
+                if then_effect.net() != else_effect.net() {
+                    self.warn(format!("branches of if have different effects {} and {}", then_effect, else_effect));
+                    return false;
+                }
+                let inputs = ::std::cmp::max(then_effect.inputs, else_effect.inputs);
+                sim.apply(Effect::new(inputs, (inputs as isize + then_effect.net()) as usize));
+            }
+            "try" => {
+                let handler = sim.pop();
+                let body = sim.pop();
+                let (body_effect, handler_effect) = match (self.item_effect(&body), self.item_effect(&handler)) {
+                    (Some(b), Some(h)) => (b, h),
+                    _ => return false,
+                };
+                if body_effect.net() != handler_effect.net() + 1 {
+                    self.warn(format!("body and handler of try have different effects {} and {}",
+                                      body_effect, handler_effect));
+                    return false;
+                }
+                sim.apply(body_effect);
+            }
+            "repeat" => {
+                sim.pop();
+                if let Item::Block(block) = sim.pop() {
+                    match self.infer_block(&block) {
+                        Some(effect) if effect.net() == 0 => sim.apply(effect),
+                        _ => return false,
+                    }
+                } else {
+                    return false;
+                }
+            }
+            "map" | "filter" => {
+                let block = sim.pop();
+                sim.pop();
+                self.check_block_arg(&name, &block, 1);
+                sim.push(Item::Unknown);
+            }
+            "fold" | "fold1" => {
+                let block = sim.pop();
+                if name == "fold" {
+                    sim.pop();
+                }
+                sim.pop();
+                self.check_block_arg(&name, &block, 2);
+                sim.push(Item::Unknown);
+            }
+            _ => match self.word_effect(&name) {
+                Some(effect) => sim.apply(effect),
+                None => return false,
+            },
+        }
+        true
+    }
+
+    /// Check that a block run with `arity` values on a fresh stack leaves
+    /// exactly one value, as required by `map`, `filter` and `fold`.
+    fn check_block_arg(&mut self, name: &str, block: &Item, arity: usize) {
+        if let Item::Block(ref block) = *block {
+            if let Some(effect) = self.infer_block(block) {
+                if effect.inputs > arity {
+                    self.warn(format!("block given to {} underflows: it takes {} values but gets {}",
+                                      name, effect.inputs, arity));
+                } else if arity - effect.inputs + effect.outputs != 1 {
+                    self.warn(format!("block given to {} must leave exactly one value, but leaves {}",
+                                      name, arity - effect.inputs + effect.outputs));
+                }
+            }
+        }
+    }
+
+    /// Effect of running a value as a branch of `if` or `try`.
+    fn item_effect(&mut self, item: &Item) -> Option<Effect> {
+        match *item {
+            Item::Block(ref block) => self.infer_block(block),
+            _ => Some(Effect::new(0, 1)),
+        }
+    }
+
+    /// Effect of a word, from its signature or by inferring its definition.
+    fn word_effect(&mut self, name: &str) -> Option<Effect> {
+        if let Some(signature) = self.dict.lookup_doc(name).and_then(|doc| doc.signature) {
+            return Effect::parse(&signature);
+        }
+        if let Some(effect) = self.effects.get(name) {
+            return *effect;
+        }
+        let op = self.dict.lookup(name)?;
+        let effect = match *op {
+            Operation::Value(Value::Block(ref block)) => {
+                if !self.in_progress.insert(name.to_owned()) {
+                    // Recursive definition
+                    return None;
+                }
+                let effect = self.infer_block(block);
+                self.in_progress.remove(name);
+                effect
+            }
+            Operation::Value(_) => Some(Effect::new(0, 1)),
+            Operation::Native(_) => None,
+        };
+        self.effects.insert(name.to_owned(), effect);
+        effect
+    }
+}
+
+/// Collect the words up to the `close` matching an already read `open`,
+/// returning them and the index after `close`.
+fn collect(words: &[Word], start: usize, open: &str, close: &str) -> (Vec<Word>, usize) {
+    let mut level = 0;
+    for (i, word) in words[start..].iter().enumerate() {
+        if word == open {
+            level += 1;
+        } else if word == close {
+            if level == 0 {
+                return (words[start..start + i].to_vec(), start + i + 1);
+            }
+            level -= 1;
+        }
+    }
+    (words[start..].to_vec(), words.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use calc::Calc;
+    use super::*;
+
+    fn warnings(source: &str) -> Vec<String> {
+        let mut calc = Calc::new();
+        calc.eval(source).unwrap();
+        calc.check().into_iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Effect::parse("( vec block -- vec )"), Some(Effect::new(2, 1)));
+        assert_eq!(Effect::parse("( -- )"), Some(Effect::new(0, 0)));
+        assert_eq!(Effect::parse("( ... f -- ... )"), None);
+    }
+
+    #[test]
+    fn test_prelude() {
+        assert_eq!(warnings(::PRELUDE), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_warnings() {
+        assert_eq!(warnings(",f { { dup } map } def"),
+                   vec!["in 'f': block given to map must leave exactly one value, but leaves 2"]);
+        assert_eq!(warnings(",f { { 1 } { 1 2 } if } def"),
+                   vec!["in 'f': branches of if have different effects ( 0 -- 1 ) and ( 0 -- 2 )"]);
+        assert_eq!(warnings(",f { add add } def ,f \",( x y -- z )\" doc"),
+                   vec!["in 'f': body underflows: declared ( x y -- z ) but takes 3 values"]);
+        assert_eq!(warnings(",f { dup 1 add } def ,f \",( x -- y )\" doc"),
+                   vec!["in 'f': declared ( x -- y ) but body has effect ( 1 -- 2 )"]);
+        assert_eq!(warnings(",f { 0 swap 1 swap { dup roll3 add } swap repeat pop } def ,f \",( n -- x )\" doc"),
+                   Vec::<String>::new());
+    }
+}
@@ -0,0 +1,165 @@
+//! Reading delimiter separated tables, e.g. CSV exported from a spreadsheet.
+
+use std::io::BufRead;
+
+use errors::*;
+use words::Value;
+
+/// Split a record into fields. Fields may be quoted with `"`, in which case
+/// they can contain the delimiter, newlines and `""` for a quote. Returns
+/// `None` if a quoted field is not terminated.
+fn split_record(record: &str, delim: char) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = record.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c == '"' {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            } else {
+                field.push(c);
+            }
+        } else if c == '"' && field.trim().is_empty() {
+            field.clear();
+            quoted = true;
+        } else if c == delim {
+            fields.push(field);
+            field = String::new();
+        } else {
+            field.push(c);
+        }
+    }
+    if quoted {
+        return None;
+    }
+    fields.push(field);
+    Some(fields)
+}
+
+fn parse_cell(cell: &str, line: usize, column: usize, warnings: &mut Vec<String>) -> Value {
+    let cell = cell.trim();
+    if cell.is_empty() {
+        return Value::Undef;
+    }
+    Value::parse(cell).unwrap_or_else(|| {
+        warnings.push(format!("line {}, column {}: '{}' is not a number", line, column, cell));
+        Value::QuotedWord(cell.to_owned())
+    })
+}
+
+/// Read a table of values separated by `delim`. Without a header the result
+/// is a vector of rows; with one it is a vector of `[ name column ]` pairs.
+/// Empty cells become undef and cells which are not numbers are kept as
+/// quoted words. Such cells, and cells beyond the columns named in the
+/// header, which are left out, are reported in the returned warnings.
+pub fn read_table<R: BufRead>(input: R, delim: char, header: bool) -> Result<(Value, Vec<String>)> {
+    let mut names: Option<Vec<Value>> = None;
+    let mut warnings = Vec::new();
+    let mut rows = Vec::new();
+    let mut record = String::new();
+    let mut start = 0;
+    for (n, line) in input.lines().enumerate() {
+        let line = line.chain_err(|| "could not read table")?;
+        if record.is_empty() {
+            start = n + 1;
+        } else {
+            record.push('\n');
+        }
+        record.push_str(if line.ends_with('\r') { &line[..line.len() - 1] } else { &line });
+        let fields = match split_record(&record, delim) {
+            Some(fields) => fields,
+            None => continue,
+        };
+        record.clear();
+        if fields.len() == 1 && fields[0].trim().is_empty() {
+            continue;
+        }
+        if header && names.is_none() {
+            names = Some(fields.into_iter().map(|f| Value::QuotedWord(f.trim().to_owned())).collect::<Vec<_>>());
+            continue;
+        }
+        if let Some(ref names) = names {
+            if fields.len() > names.len() {
+                warnings.push(format!("line {}: {} cells but the header names {} columns, the rest are left out",
+                                      start, fields.len(), names.len()));
+            }
+        }
+        rows.push(fields.iter().enumerate().map(|(i, f)| parse_cell(f, start, i + 1, &mut warnings)).collect::<Vec<_>>());
+    }
+    if !record.is_empty() {
+        bail!("unterminated quoted field starting on line {}", start);
+    }
+
+    let table = match names {
+        None => Value::Vector(rows.into_iter().map(Value::Vector).collect()),
+        Some(names) => {
+            let columns = names.into_iter().enumerate().map(|(i, name)| {
+                let column = rows.iter().map(|row| row.get(i).cloned().unwrap_or(Value::Undef)).collect();
+                Value::Vector(vec![name, Value::Vector(column)])
+            }).collect();
+            Value::Vector(columns)
+        }
+    };
+    Ok((table, warnings))
+}
+
+/// The delimiter named by `word`, which is a single character or `\t` for tab.
+pub fn delimiter(word: &str) -> Option<char> {
+    if word == "\\t" {
+        return Some('\t');
+    }
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num::BigInt;
+    use super::*;
+
+    fn int(i: i64) -> Value {
+        Value::Int(BigInt::from(i))
+    }
+
+    fn word(w: &str) -> Value {
+        Value::QuotedWord(w.to_owned())
+    }
+
+    #[test]
+    fn test_split_record() {
+        assert_eq!(split_record(r#"1,"a, b",,"say ""hi""""#, ','),
+                   Some(vec!["1".to_owned(), "a, b".to_owned(), "".to_owned(), r#"say "hi""#.to_owned()]));
+        assert_eq!(split_record("1,\"open", ','), None);
+    }
+
+    #[test]
+    fn test_read_table() {
+        let input = "1;2.5\n\n3;x;\n";
+        assert_eq!(read_table(input.as_bytes(), ';', false).unwrap(),
+                   (Value::Vector(vec![Value::Vector(vec![int(1), Value::Float(2.5)]),
+                                       Value::Vector(vec![int(3), word("x"), Value::Undef])]),
+                    vec!["line 3, column 2: 'x' is not a number".to_owned()]));
+
+        let input = "a,\"b\nc\"\r\n1,2\n3\n";
+        assert_eq!(read_table(input.as_bytes(), ',', true).unwrap(),
+                   (Value::Vector(vec![Value::Vector(vec![word("a"), Value::Vector(vec![int(1), int(3)])]),
+                                       Value::Vector(vec![word("b\nc"), Value::Vector(vec![int(2), Value::Undef])])]),
+                    vec![]));
+
+        let input = "a\n1,2\n";
+        assert_eq!(read_table(input.as_bytes(), ',', true).unwrap(),
+                   (Value::Vector(vec![Value::Vector(vec![word("a"), Value::Vector(vec![int(1)])])]),
+                    vec!["line 2: 2 cells but the header names 1 columns, the rest are left out".to_owned()]));
+
+        assert!(read_table("1,\"2\n".as_bytes(), ',', false).is_err());
+    }
+}
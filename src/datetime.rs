@@ -0,0 +1,389 @@
+//! Dates, times and durations, with a resolution of one second.
+//!
+//! Times are in UTC. A date is written `2026-10-17` or `2026-10-17T09:30:00`
+//! and a duration as numbers with units, e.g. `3d4h`, `90min` or `-2w`.
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use calc::Calc;
+use errors::*;
+use words::Value;
+
+const MINUTE: i64 = 60;
+const HOUR: i64 = 60 * MINUTE;
+const DAY: i64 = 24 * HOUR;
+const WEEK: i64 = 7 * DAY;
+
+/// Units of durations, largest first.
+const UNITS: [(&str, i64); 5] = [("w", WEEK), ("d", DAY), ("h", HOUR), ("min", MINUTE), ("s", 1)];
+
+const WEEKDAYS: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July",
+                            "August", "September", "October", "November", "December"];
+
+/// A point in time, as seconds since 1970-01-01T00:00:00 UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime(pub i64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Duration(pub i64);
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// The year, month and day of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = if days >= 0 { days } else { days - 146_096 } / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (year_of_era + era * 400 + if month <= 2 { 1 } else { 0 }, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    days_from_civil(year + month / 12, month % 12 + 1, 1) - days_from_civil(year, month, 1)
+}
+
+/// Parse `digits` as a number of exactly `len` digits.
+fn number(digits: &str, len: usize) -> Option<i64> {
+    if digits.len() != len || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn overflow() -> Error {
+    "date or duration out of range".into()
+}
+
+impl DateTime {
+    pub fn now() -> DateTime {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64);
+        DateTime(since_epoch.unwrap_or(0))
+    }
+
+    /// Parse `YYYY-MM-DD`, optionally followed by `THH:MM` or `THH:MM:SS`.
+    pub fn parse(s: &str) -> Option<DateTime> {
+        let (date, time) = match s.find('T') {
+            Some(t) => (&s[..t], Some(&s[t + 1..])),
+            None => (s, None),
+        };
+        let mut parts = date.split('-');
+        let year = number(parts.next()?, 4)?;
+        let month = number(parts.next()?, 2)?;
+        let day = number(parts.next()?, 2)?;
+        if parts.next().is_some() || month < 1 || month > 12 || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        let mut seconds = days_from_civil(year, month, day) * DAY;
+        if let Some(time) = time {
+            let mut parts = time.split(':');
+            let hour = number(parts.next()?, 2)?;
+            let minute = number(parts.next()?, 2)?;
+            let second = match parts.next() {
+                Some(second) => number(second, 2)?,
+                None => 0,
+            };
+            if parts.next().is_some() || hour > 23 || minute > 59 || second > 59 {
+                return None;
+            }
+            seconds += hour * HOUR + minute * MINUTE + second;
+        }
+        Some(DateTime(seconds))
+    }
+
+    fn days(&self) -> i64 {
+        div_floor(self.0, DAY)
+    }
+
+    /// Day of the week, 0 for Monday to 6 for Sunday.
+    pub fn weekday(&self) -> usize {
+        // 1970-01-01 was a Thursday
+        ((self.days() + 3).rem_euclid(7)) as usize
+    }
+
+    /// Format with strftime-like specifiers: `%Y %m %d %H %M %S %j %a %A %b
+    /// %B %u` and `%%`.
+    pub fn format(&self, format: &str) -> Result<String> {
+        let (year, month, day) = civil_from_days(self.days());
+        let seconds = self.0.rem_euclid(DAY);
+        let mut out = String::new();
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                out.push(c);
+                continue;
+            }
+            let spec = chars.next().ok_or("format ends with '%'")?;
+            let text = match spec {
+                'Y' => format!("{:04}", year),
+                'm' => format!("{:02}", month),
+                'd' => format!("{:02}", day),
+                'H' => format!("{:02}", seconds / HOUR),
+                'M' => format!("{:02}", seconds % HOUR / MINUTE),
+                'S' => format!("{:02}", seconds % MINUTE),
+                'j' => format!("{:03}", self.days() - days_from_civil(year, 1, 1) + 1),
+                'a' => WEEKDAYS[self.weekday()][..3].to_owned(),
+                'A' => WEEKDAYS[self.weekday()].to_owned(),
+                'b' => MONTHS[month as usize - 1][..3].to_owned(),
+                'B' => MONTHS[month as usize - 1].to_owned(),
+                'u' => (self.weekday() + 1).to_string(),
+                '%' => "%".to_owned(),
+                c => bail!("unknown format specifier '%{}'", c),
+            };
+            out.push_str(&text);
+        }
+        Ok(out)
+    }
+}
+
+fn div_floor(x: i64, y: i64) -> i64 {
+    (x - x.rem_euclid(y)) / y
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = if self.0.rem_euclid(DAY) == 0 { "%Y-%m-%d" } else { "%Y-%m-%dT%H:%M:%S" };
+        write!(f, "{}", self.format(format).map_err(|_| fmt::Error)?)
+    }
+}
+
+impl Duration {
+    /// Parse numbers followed by units, e.g. `1h30min`.
+    pub fn parse(s: &str) -> Option<Duration> {
+        let (sign, mut rest) = if s.starts_with('-') { (-1, &s[1..]) } else { (1, s) };
+        if rest.is_empty() {
+            return None;
+        }
+        let mut seconds: i64 = 0;
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let letters = rest[digits..].find(|c: char| c.is_ascii_digit()).map_or(rest.len(), |i| i + digits);
+            let count: i64 = rest[..digits].parse().ok()?;
+            let &(_, unit) = UNITS.iter().find(|&&(name, _)| name == &rest[digits..letters])?;
+            seconds = seconds.checked_add(count.checked_mul(unit)?)?;
+            rest = &rest[letters..];
+        }
+        Some(Duration(sign * seconds))
+    }
+}
+
+impl fmt::Display for Duration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.0 == 0 {
+            return write!(f, "0s");
+        }
+        if self.0 < 0 {
+            write!(f, "-")?;
+        }
+        let mut rest = self.0.unsigned_abs();
+        // Weeks are left out, they are easier to read as days
+        for &(name, unit) in &UNITS[1..] {
+            let unit = unit as u64;
+            if rest >= unit {
+                write!(f, "{}{}", rest / unit, name)?;
+                rest %= unit;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn is_time(value: &Value) -> bool {
+    match *value {
+        Value::DateTime(_) | Value::Duration(_) => true,
+        _ => false,
+    }
+}
+
+/// The error for combining `x` and `y`, blaming the one which is not a time.
+fn wrong_type(x: Value, y: Value) -> Error {
+    let value = if is_time(&x) { y } else { x };
+    ErrorKind::WrongTypeOperand(value, "date or duration").into()
+}
+
+fn seconds(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Duration(Duration(s)) => Some(s as f64),
+        _ => value.as_float_cast(),
+    }
+}
+
+fn scaled(d: Duration, factor: f64) -> Result<Value> {
+    let seconds = (d.0 as f64 * factor).round();
+    if !seconds.is_finite() || seconds.abs() >= i64::max_value() as f64 {
+        return Err(overflow());
+    }
+    Ok(Value::Duration(Duration(seconds as i64)))
+}
+
+pub fn add(x: Value, y: Value) -> Result<Value> {
+    Ok(match (x, y) {
+        (Value::DateTime(t), Value::Duration(d)) | (Value::Duration(d), Value::DateTime(t)) => {
+            Value::DateTime(DateTime(t.0.checked_add(d.0).ok_or_else(overflow)?))
+        }
+        (Value::Duration(a), Value::Duration(b)) => Value::Duration(Duration(a.0.checked_add(b.0).ok_or_else(overflow)?)),
+        (x, y) => return Err(wrong_type(x, y)),
+    })
+}
+
+pub fn sub(x: Value, y: Value) -> Result<Value> {
+    Ok(match (x, y) {
+        (Value::DateTime(a), Value::DateTime(b)) => Value::Duration(Duration(a.0.checked_sub(b.0).ok_or_else(overflow)?)),
+        (Value::DateTime(t), Value::Duration(d)) => Value::DateTime(DateTime(t.0.checked_sub(d.0).ok_or_else(overflow)?)),
+        (Value::Duration(a), Value::Duration(b)) => Value::Duration(Duration(a.0.checked_sub(b.0).ok_or_else(overflow)?)),
+        (x, y) => return Err(wrong_type(x, y)),
+    })
+}
+
+pub fn mul(x: Value, y: Value) -> Result<Value> {
+    match (x, y) {
+        (Value::Duration(d), n) | (n, Value::Duration(d)) => match n.as_float_cast() {
+            Some(factor) => scaled(d, factor),
+            None => Err(ErrorKind::WrongTypeOperand(n, "int or float").into()),
+        },
+        (x, y) => Err(wrong_type(x, y)),
+    }
+}
+
+/// Divide a duration by a duration, giving a float, or by a number.
+pub fn div(x: Value, y: Value) -> Result<Value> {
+    let d = match x {
+        Value::Duration(d) => d,
+        x => return Err(wrong_type(x, y)),
+    };
+    let divisor = seconds(&y).ok_or_else(|| Error::from(ErrorKind::WrongTypeOperand(y.clone(), "duration or number")))?;
+    if divisor == 0.0 {
+        return Err(ErrorKind::DivisionByZero.into());
+    }
+    match y {
+        Value::Duration(_) => Ok(Value::Float(d.0 as f64 / divisor)),
+        _ => scaled(d, 1.0 / divisor),
+    }
+}
+
+impl Calc {
+    fn get_datetime(&mut self) -> Result<DateTime> {
+        match self.get_operand()? {
+            Value::DateTime(t) => Ok(t),
+            v => Err(ErrorKind::WrongTypeOperand(v, "date").into()),
+        }
+    }
+
+    pub fn builtin_now(&mut self) -> Result<()> {
+        self.data.push(Value::DateTime(DateTime::now()));
+        Ok(())
+    }
+
+    pub fn builtin_today(&mut self) -> Result<()> {
+        let now = DateTime::now();
+        self.data.push(Value::DateTime(DateTime(now.days() * DAY)));
+        Ok(())
+    }
+
+    pub fn builtin_strftime(&mut self) -> Result<()> {
+        let format = self.get_word()?;
+        let t = self.get_datetime()?;
+        self.data.push(Value::QuotedWord(t.format(&format)?));
+        Ok(())
+    }
+
+    pub fn builtin_weekday(&mut self) -> Result<()> {
+        let t = self.get_datetime()?;
+        self.data.push(Value::Int((t.weekday() + 1).into()));
+        Ok(())
+    }
+
+    /// Number of days from Monday to Friday from the first date up to, but
+    /// not including, the second.
+    pub fn builtin_workdays(&mut self) -> Result<()> {
+        let to = self.get_datetime()?.days();
+        let from = self.get_datetime()?.days();
+        let (start, end, sign) = if from <= to { (from, to, 1) } else { (to, from, -1) };
+        let weeks = (end - start) / 7;
+        let mut count = weeks * 5;
+        for day in start + weeks * 7..end {
+            if DateTime(day * DAY).weekday() < 5 {
+                count += 1;
+            }
+        }
+        self.data.push(Value::Int((sign * count).into()));
+        Ok(())
+    }
+
+    pub fn builtin_duration(&mut self) -> Result<()> {
+        let value = self.get_operand()?;
+        match value.as_float_cast() {
+            Some(seconds) => {
+                let value = scaled(Duration(1), seconds)?;
+                self.data.push(value);
+                Ok(())
+            }
+            None => Err(ErrorKind::WrongTypeOperand(value, "int or float").into()),
+        }
+    }
+
+    pub fn builtin_seconds(&mut self) -> Result<()> {
+        match self.get_operand()? {
+            Value::Duration(d) => self.data.push(Value::Int(d.0.into())),
+            Value::DateTime(t) => self.data.push(Value::Int(t.0.into())),
+            v => return Err(ErrorKind::WrongTypeOperand(v, "date or duration").into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{assert_cases, assert_errors};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(DateTime::parse("1970-01-02"), Some(DateTime(DAY)));
+        assert_eq!(DateTime::parse("2000-03-01T01:02:03"), Some(DateTime(951_872_523)));
+        assert_eq!(DateTime::parse("1969-12-31T23:59"), Some(DateTime(-60)));
+        assert_eq!(DateTime::parse("2023-02-29"), None);
+        assert_eq!(DateTime::parse("2024-02-29").map(|t| t.to_string()), Some("2024-02-29".to_owned()));
+        assert_eq!(Duration::parse("3d4h"), Some(Duration(3 * DAY + 4 * HOUR)));
+        assert_eq!(Duration::parse("-90min"), Some(Duration(-90 * MINUTE)));
+        assert_eq!(Duration::parse("2w").map(|d| d.to_string()), Some("14d".to_owned()));
+        assert_eq!(Duration::parse("5"), None);
+        assert_eq!(Duration::parse("5m"), None);
+    }
+
+    #[test]
+    fn test_datetime() {
+        assert_cases(&[
+            ("2026-10-17 2026-10-01 -", "16d"),
+            ("2026-10-17 3d4h +", "2026-10-20T04:00:00"),
+            ("1h 2026-01-01 +", "2026-01-01T01:00:00"),
+            ("2026-01-01 1s -", "2025-12-31T23:59:59"),
+            ("90min 2 .", "3h"),
+            ("3h 90min div 3h 4 div", "2.0 45min"),
+            ("2026-10-17 1000000 duration +", "2026-10-28T13:46:40"),
+            (r#"2026-10-17T08:05:00 ",%a %d %B %Y %H:%M" strftime"#, r#"",Sat 17 October 2026 08:05""#),
+            ("2026-10-17 weekday 2026-10-19 weekday", "6 1"),
+            ("2026-10-16 2026-10-26 workdays 2026-10-26 2026-10-16 workdays", "6 -6"),
+            ("2026-10-17 2026-10-18 cmp 1h 60min eq", "-1 1"),
+            ("90min seconds", "5400"),
+        ]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_errors(&["2026-10-17 2026-10-17 +", "2026-10-17 1 +", "1h 0 div", "2026-10-17 ,%Q strftime"]);
+    }
+}
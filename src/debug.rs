@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::rc::Rc;
+
+use words::{Operation, Value, Word};
+
+/// A defined word whose block is being executed.
+pub struct Frame {
+    pub word: Word,
+    pub op: Rc<Operation>,
+}
+
+/// Tracing and debugging state, shared by a calculator and its sub-calculators.
+pub struct Debugger {
+    pub trace: bool,
+    pub stepping: bool,
+    /// Names of the words to pause at, with aliases resolved.
+    pub breakpoints: HashSet<Word>,
+    pub frames: Vec<Frame>,
+    /// Where trace lines, debugger messages and warnings of words are
+    /// written, standard error by default.
+    pub output: Box<dyn Write>,
+    /// Where debugger commands are read from, the terminal by default.
+    pub input: Option<Box<dyn BufRead>>,
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger {
+            trace: false,
+            stepping: false,
+            breakpoints: HashSet::new(),
+            frames: Vec::new(),
+            output: Box::new(io::stderr()),
+            input: None,
+        }
+    }
+}
+
+impl Debugger {
+    /// Write a line of debugger output. Failing to write is not an error of
+    /// the evaluation, so it is ignored.
+    pub fn say(&mut self, line: &str) {
+        let _ = writeln!(self.output, "{}", line);
+    }
+
+    /// Write a trace line for `word`, which took the stack from `before` to
+    /// `after`.
+    pub fn trace_word(&mut self, word: &str, before: &str, after: &str) {
+        let line = format!("{}{} {} -> {}", self.indent(), word, before, after);
+        self.say(&line);
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Prefix of a trace line for the current call depth.
+    pub fn indent(&self) -> String {
+        format!("{:2} {}", self.depth(), "  ".repeat(self.depth()))
+    }
+}
+
+pub enum Command {
+    Step,
+    Continue,
+    Stack,
+    Where,
+    Inspect(Word),
+    Quit,
+    Help,
+}
+
+pub const HELP: &str = "\
+commands:
+  s, step          execute the next word and pause again
+  c, continue      continue until the next breakpoint
+  p, stack         show the stack
+  w, where         show the words being executed
+  i, inspect WORD  show the definition of a word
+  q, quit          abort the evaluation";
+
+impl Debugger {
+    /// Prompt for a debugger command on the input, or on the terminal,
+    /// falling back to standard input. Returns `None` at end of input.
+    pub fn read_command(&mut self) -> io::Result<Option<Command>> {
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            let n = match self.input {
+                Some(ref mut input) => input.read_line(&mut line)?,
+                None => match File::open("/dev/tty") {
+                    Ok(tty) => BufReader::new(tty).read_line(&mut line)?,
+                    Err(_) => io::stdin().read_line(&mut line)?,
+                },
+            };
+            if n == 0 {
+                return Ok(None);
+            }
+            if let Some(cmd) = self.parse_command(&line) {
+                return Ok(Some(cmd));
+            }
+        }
+    }
+
+    fn parse_command(&mut self, line: &str) -> Option<Command> {
+        let mut parts = line.split_whitespace();
+        let cmd = match (parts.next(), parts.next()) {
+            (None, _) | (Some("s"), _) | (Some("step"), _) => Command::Step,
+            (Some("c"), _) | (Some("continue"), _) => Command::Continue,
+            (Some("p"), _) | (Some("stack"), _) => Command::Stack,
+            (Some("w"), _) | (Some("where"), _) => Command::Where,
+            (Some("i"), Some(word)) | (Some("inspect"), Some(word)) => Command::Inspect(word.to_owned()),
+            (Some("q"), _) | (Some("quit"), _) => Command::Quit,
+            (Some("h"), _) | (Some("help"), _) => Command::Help,
+            (Some(other), _) => {
+                self.say(&format!("unknown command '{}', type h for help", other));
+                return None;
+            }
+        };
+        Some(cmd)
+    }
+}
+
+pub fn format_stack(data: &[Value]) -> String {
+    let values: Vec<String> = data.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(" "))
+}
+
+pub fn format_block(block: &[Word]) -> String {
+    format!("{{ {} }}", block.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::{self, Cursor, Write};
+    use std::rc::Rc;
+
+    use calc::Calc;
+
+    /// Debugger output which stays readable after the debugger takes it.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Evaluate `source` answering the debugger with `commands`, and return
+    /// whether it succeeded, the stack and the debugger output.
+    fn debug(source: &str, trace: bool, commands: &str) -> (bool, String, String) {
+        let mut calc = Calc::with_prelude().unwrap();
+        let output = Output::default();
+        {
+            let mut debugger = calc.debugger.borrow_mut();
+            debugger.trace = trace;
+            debugger.output = Box::new(output.clone());
+            debugger.input = Some(Box::new(Cursor::new(commands.to_owned())));
+        }
+        let ok = calc.eval(source).is_ok();
+        let text = String::from_utf8(output.0.borrow().clone()).unwrap();
+        (ok, super::format_stack(&calc.data), text)
+    }
+
+    #[test]
+    fn test_trace() {
+        let (ok, stack, output) = debug(",sq { dup . } def 3 sq", true, "");
+        assert!(ok);
+        assert_eq!(stack, "[9]");
+        assert_eq!(output.lines().skip(3).collect::<Vec<_>>(), [
+            " 0 3 [] -> [3]",
+            " 0 sq [3] -> ...",
+            " 1   dup [3] -> [3 3]",
+            " 1   . [3 3] -> [9]",
+            " 0 sq [3] -> [9]",
+        ]);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        // A breakpoint on a word stops at its aliases, and the other way round
+        let source = ",sq { dup . } def ,square ,sq alias ,square break 2 sq ,mul break 3 .";
+        let (ok, stack, output) = debug(source, false, "p\nc\nc\n");
+        assert!(ok);
+        assert_eq!(stack, "[12]");
+        assert_eq!(output, "paused before 'sq'\n  stack: [2]\n(debug) [2]\n(debug) \
+                            paused before '.'\n  stack: [4 3]\n(debug) ");
+
+        let (ok, _, _) = debug(",nosuchword break", false, "");
+        assert!(!ok);
+    }
+
+    #[test]
+    fn test_stepping() {
+        let (ok, stack, output) = debug(",sq { dup . } def 2 debug sq 1 +", false, "s\ns\nw\nbogus\ni sq\nq\n");
+        assert!(!ok);
+        assert_eq!(stack, "[2]");
+        assert_eq!(output.lines().collect::<Vec<_>>(), [
+            "paused before 'debug'",
+            "  stack: [2]",
+            "(debug) paused before 'sq'",
+            "  stack: [2]",
+            "(debug) paused before 'dup' in 'sq' (depth 1)",
+            "  block: { dup . }",
+            "  stack: [2]",
+            "(debug)   in 'sq'",
+            "(debug) unknown command 'bogus', type h for help",
+            "(debug) sq",
+            "    (undocumented)",
+            "  = { dup . }",
+            "(debug) ",
+        ]);
+    }
+}
@@ -2,16 +2,50 @@
 use std::rc::Rc;
 use std::cell::RefCell;
 use std::collections::{HashMap, BTreeMap};
-use words::{Word, BuiltinWord, Operation};
+use std::ops::{Add, Mul, Sub};
 
-#[derive(Debug, PartialEq)]
-enum Entry {
+use calc::Calc;
+use datetime;
+use errors::Result;
+use words::{Native, Operation, Value, Word};
+
+/// Documentation attached to a word.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Doc {
+    /// Stack effect, e.g. `( vec block -- vec )`.
+    pub signature: Option<String>,
+    pub text: String,
+}
+
+impl Doc {
+    /// Parse a doc string, taking a leading `( ... -- ... )` as the signature.
+    pub fn parse(s: &str) -> Doc {
+        let s = s.trim();
+        if s.starts_with('(') {
+            if let Some(end) = s.find(')') {
+                return Doc {
+                    signature: Some(s[..end + 1].to_owned()),
+                    text: s[end + 1..].trim().to_owned(),
+                };
+            }
+        }
+        Doc {
+            signature: None,
+            text: s.to_owned(),
+        }
+    }
+}
+
+/// A word defined in a dictionary.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
     Alias(Word),
     Op(Rc<Operation>),
 }
 
 struct Inner {
     map: HashMap<Word, Entry>,
+    docs: HashMap<Word, Doc>,
     parent: Option<Rc<RefCell<Inner>>>,
 }
 
@@ -36,6 +70,25 @@ impl Inner {
         }
     }
 
+    fn resolve(&self, word: &str) -> Option<Word> {
+        match self.map.get(word) {
+            Some(&Entry::Alias(ref other)) => self.resolve(other),
+            Some(&Entry::Op(_)) => Some(word.to_owned()),
+            None => self.parent.as_ref().and_then(|p| p.borrow().resolve(word)),
+        }
+    }
+
+    fn lookup_doc(&self, word: &str) -> Option<Doc> {
+        if let Some(doc) = self.docs.get(word) {
+            return Some(doc.clone());
+        }
+        match self.map.get(word) {
+            Some(&Entry::Alias(ref other)) => self.lookup_doc(other),
+            Some(&Entry::Op(_)) => None,
+            None => self.parent.as_ref().and_then(|p| p.borrow().lookup_doc(word)),
+        }
+    }
+
     fn available_words(&self) -> BTreeMap<Word, Vec<Word>> {
         let mut words = BTreeMap::new();
         for (word, entry) in &self.map {
@@ -75,6 +128,7 @@ impl Dictionary {
         Dictionary {
             inner: Rc::new(RefCell::new(Inner {
                 map: HashMap::new(),
+                docs: HashMap::new(),
                 parent: None,
             })),
         }
@@ -84,6 +138,7 @@ impl Dictionary {
         Dictionary {
             inner: Rc::new(RefCell::new(Inner {
                 map: HashMap::new(),
+                docs: HashMap::new(),
                 parent: Some(Rc::clone(&dict.inner)),
             })),
         }
@@ -92,13 +147,58 @@ impl Dictionary {
     pub fn insert<T>(&mut self, word: T, op: Operation)
         where String: From<T>
     {
-        self.inner.borrow_mut().map.insert(From::from(word), Entry::Op(Rc::new(op)));
+        let word = String::from(word);
+        let mut inner = self.inner.borrow_mut();
+        inner.docs.remove(&word);
+        inner.map.insert(word, Entry::Op(Rc::new(op)));
     }
 
     pub fn insert_alias<T>(&mut self, word: T, other: T)
         where String: From<T>
     {
-        self.inner.borrow_mut().map.insert(From::from(word), Entry::Alias(From::from(other)));
+        let word = String::from(word);
+        let mut inner = self.inner.borrow_mut();
+        inner.docs.remove(&word);
+        inner.map.insert(word, Entry::Alias(From::from(other)));
+    }
+
+    fn insert_native<F>(&mut self, word: &str, signature: &str, text: &str, f: F)
+        where F: Fn(&mut Calc) -> Result<()> + 'static
+    {
+        self.insert_documented(word, Native::new(word, f), signature, text);
+    }
+
+    fn insert_io_native<F>(&mut self, word: &str, signature: &str, text: &str, f: F)
+        where F: Fn(&mut Calc) -> Result<()> + 'static
+    {
+        let native = Native {
+            io: true,
+            ..Native::new(word, f)
+        };
+        self.insert_documented(word, native, signature, text);
+    }
+
+    fn insert_documented(&mut self, word: &str, native: Native, signature: &str, text: &str) {
+        self.insert(word, Operation::Native(native));
+        self.set_doc(word, Doc {
+            signature: Some(signature.to_owned()),
+            text: text.to_owned(),
+        });
+    }
+
+    pub fn set_doc<T>(&mut self, word: T, doc: Doc)
+        where String: From<T>
+    {
+        self.inner.borrow_mut().docs.insert(From::from(word), doc);
+    }
+
+    pub fn lookup_doc(&self, word: &str) -> Option<Doc> {
+        self.inner.borrow().lookup_doc(word)
+    }
+
+    /// Follow aliases to the name under which `word` is actually defined.
+    pub fn resolve(&self, word: &str) -> Option<Word> {
+        self.inner.borrow().resolve(word)
     }
 
     pub fn lookup(&self, word: &str) -> Option<Rc<Operation>> {
@@ -108,49 +208,325 @@ impl Dictionary {
     pub fn available_words(&self) -> BTreeMap<Word, Vec<Word>> {
         self.inner.borrow().available_words()
     }
+
+    /// Entries defined in this dictionary itself, not its parents, sorted by name.
+    pub fn own_entries(&self) -> Vec<(Word, Entry)> {
+        let mut entries: Vec<_> = self.inner.borrow().map.iter()
+            .map(|(word, entry)| (word.clone(), entry.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+
+    /// Documentation set in this dictionary itself, sorted by name.
+    pub fn own_docs(&self) -> Vec<(Word, Doc)> {
+        let mut docs: Vec<_> = self.inner.borrow().docs.iter()
+            .map(|(word, doc)| (word.clone(), doc.clone()))
+            .collect();
+        docs.sort_by(|a, b| a.0.cmp(&b.0));
+        docs
+    }
 }
 
 impl Default for Dictionary {
     fn default() -> Dictionary {
         let mut dict = Dictionary::new();
-        dict.insert("acos",   Operation::Builtin(BuiltinWord::Acos));
-        dict.insert("add",    Operation::Builtin(BuiltinWord::Add));
-        dict.insert("alias",  Operation::Builtin(BuiltinWord::Alias));
-        dict.insert("apply",  Operation::Builtin(BuiltinWord::Apply));
-        dict.insert("arg",    Operation::Builtin(BuiltinWord::Arg));
-        dict.insert("asin",   Operation::Builtin(BuiltinWord::Asin));
-        dict.insert("atan",   Operation::Builtin(BuiltinWord::Atan));
-        dict.insert("cmp",    Operation::Builtin(BuiltinWord::Cmp));
-        dict.insert("cos",    Operation::Builtin(BuiltinWord::Cos));
-        dict.insert("def",    Operation::Builtin(BuiltinWord::Def));
-        dict.insert("div",    Operation::Builtin(BuiltinWord::Div));
-        dict.insert("dump",   Operation::Builtin(BuiltinWord::Dump));
-        dict.insert("dup",    Operation::Builtin(BuiltinWord::Duplicate));
-        dict.insert("exp",    Operation::Builtin(BuiltinWord::Exp));
-        dict.insert("filter", Operation::Builtin(BuiltinWord::Filter));
-        dict.insert("fold",   Operation::Builtin(BuiltinWord::Fold));
-        dict.insert("fold1",  Operation::Builtin(BuiltinWord::Fold1));
-        dict.insert("if",     Operation::Builtin(BuiltinWord::If));
-        dict.insert("len",    Operation::Builtin(BuiltinWord::Length));
-        dict.insert("ln",     Operation::Builtin(BuiltinWord::Ln));
-        dict.insert("log",    Operation::Builtin(BuiltinWord::Log));
-        dict.insert("map",    Operation::Builtin(BuiltinWord::Map));
-        dict.insert("max",    Operation::Builtin(BuiltinWord::Max));
-        dict.insert("min",    Operation::Builtin(BuiltinWord::Min));
-        dict.insert("mod",    Operation::Builtin(BuiltinWord::Mod));
-        dict.insert("mul",    Operation::Builtin(BuiltinWord::Mul));
-        dict.insert("over",   Operation::Builtin(BuiltinWord::Over));
-        dict.insert("pop",    Operation::Builtin(BuiltinWord::Pop));
-        dict.insert("pow",    Operation::Builtin(BuiltinWord::Pow));
-        dict.insert("print",  Operation::Builtin(BuiltinWord::Print));
-        dict.insert("repeat", Operation::Builtin(BuiltinWord::Repeat));
-        dict.insert("roll3",  Operation::Builtin(BuiltinWord::Roll3));
-        dict.insert("sin",    Operation::Builtin(BuiltinWord::Sin));
-        dict.insert("sqrt",   Operation::Builtin(BuiltinWord::Sqrt));
-        dict.insert("stdin",  Operation::Builtin(BuiltinWord::Stdin));
-        dict.insert("sub",    Operation::Builtin(BuiltinWord::Sub));
-        dict.insert("swap",   Operation::Builtin(BuiltinWord::Swap));
-        dict.insert("tan",    Operation::Builtin(BuiltinWord::Tan));
+        dict.insert_native("acos",   "( x -- y )", "Arc cosine of x, as an angle.",
+                           |calc| calc.perform_inverse_trig(f64::acos));
+        dict.insert_native("abs",    "( x -- y )", "Absolute value.",
+                           Calc::builtin_abs);
+        dict.insert_native("acosh",  "( x -- y )", "Inverse hyperbolic cosine.",
+                           |calc| calc.perform_unary(f64::acosh));
+        dict.insert_native("add",    "( x y -- z )", "Add two numbers, element-wise for vectors.",
+                           |calc| calc.perform_binop(Add::add, Add::add, Add::add, datetime::add));
+        dict.insert_native("alias",  "( name word -- )", "Make name an alias for the existing word.",
+                           Calc::builtin_alias);
+        dict.insert_native("all",    "( seq block -- b )", "Whether the block leaves a true value for every element.",
+                           Calc::builtin_all);
+        dict.insert_native("amortize", "( rate n pv -- rows )", "Repayment schedule of a loan, as [ period interest principal balance ] rows.",
+                           Calc::builtin_amortize);
+        dict.insert_native("any",    "( seq block -- b )", "Whether the block leaves a true value for some element.",
+                           Calc::builtin_any);
+        dict.insert_native("apply",  "( ... f -- ... )", "Execute a block or quoted word.",
+                           Calc::builtin_apply);
+        dict.insert_documented("arg", Native::arg(), "( -- x )",
+                               "Inside [ ], take a value from the stack outside the vector.");
+        dict.insert_native("asin",   "( x -- y )", "Arc sine of x, as an angle.",
+                           |calc| calc.perform_inverse_trig(f64::asin));
+        dict.insert_native("asinh",  "( x -- y )", "Inverse hyperbolic sine.",
+                           |calc| calc.perform_unary(f64::asinh));
+        dict.insert_native("assert", "( x -- )", "Fail unless x is true (non-zero).",
+                           Calc::builtin_assert);
+        dict.insert_native("assert-eq", "( actual expected -- )", "Fail unless the two values are equal.",
+                           Calc::builtin_assert_eq);
+        dict.insert_native("atan",   "( x -- y )", "Arc tangent of x, as an angle.",
+                           |calc| calc.perform_inverse_trig(f64::atan));
+        dict.insert_native("atan2",  "( y x -- z )", "Angle of the point (x, y) from the positive x axis.",
+                           Calc::builtin_atan2);
+        dict.insert_native("atanh",  "( x -- y )", "Inverse hyperbolic tangent.",
+                           |calc| calc.perform_unary(f64::atanh));
+        dict.insert_io_native("break",  "( name -- )", "Pause in the debugger whenever the word is executed.",
+                              Calc::builtin_break);
+        dict.insert_native("choice", "( vec -- x )", "A random element of a non-empty vector.",
+                           Calc::builtin_choice);
+        dict.insert_native("cmp",    "( x y -- n )", "Compare x with y, giving -1, 0 or 1 (undef if incomparable).",
+                           Calc::builtin_cmp);
+        dict.insert_native("collect", "( seq -- vec )", "All the elements of a sequence, as a vector.",
+                           Calc::builtin_collect);
+        dict.insert_native("concat", "( vec vec -- vec )", "The elements of both vectors.",
+                           Calc::builtin_concat);
+        dict.insert_native("cos",    "( x -- y )", "Cosine of the angle x.",
+                           |calc| calc.perform_trig(f64::cos));
+        dict.insert_native("cosh",   "( x -- y )", "Hyperbolic cosine.",
+                           |calc| calc.perform_unary(f64::cosh));
+        dict.insert_native("count",  "( seq block -- n )", "Number of elements for which the block leaves a true value.",
+                           Calc::builtin_count);
+        dict.insert_native("cross",  "( u v -- w )", "Cross product of two vectors of length 3.",
+                           Calc::builtin_cross);
+        dict.insert_io_native("debug",  "( -- )", "Pause in the debugger to step through execution and inspect the stack.",
+                              |calc| calc.pause("debug"));
+        dict.insert_native("def",    "( name value -- )", "Define name as value. Blocks are executed when the word is used.",
+                           Calc::builtin_def);
+        dict.insert_native("deg",    "( -- )", "Take and give angles in degrees.",
+                           Calc::builtin_deg);
+        dict.insert_native("deriv",  "( x block -- y )", "Numerical derivative of the block at x.",
+                           Calc::builtin_deriv);
+        dict.insert_native("det",    "( m -- x )", "Determinant of a square matrix, exact for ints and rationals.",
+                           Calc::builtin_det);
+        dict.insert_native("div",    "( x y -- z )", "Divide x by y, element-wise for vectors.",
+                           Calc::builtin_div);
+        dict.insert_native("doc",    "( name text -- )", "Document a word. A leading ( ... -- ... ) in text is its stack effect.",
+                           Calc::builtin_doc);
+        dict.insert_native("dot",    "( u v -- x )", "Dot product of two vectors.",
+                           Calc::builtin_dot);
+        dict.insert_native("drop",   "( seq n -- seq )", "All but the first n elements.",
+                           Calc::builtin_drop);
+        dict.insert_io_native("dsv",    "( delim header -- table )", "Read delimited rows from standard input; with a header, named columns.",
+                              Calc::builtin_dsv);
+        dict.insert_io_native("dump",   "( -- )", "Print the whole stack.",
+                              |calc| calc.print_stack());
+        dict.insert_native("dup",    "( x -- x x )", "Duplicate the top value.",
+                           Calc::builtin_duplicate);
+        dict.insert_native("duration", "( x -- duration )", "A duration of x seconds.",
+                           Calc::builtin_duration);
+        dict.insert_native("eng",    "( n -- )", "Print floats in engineering notation with n decimals.",
+                           Calc::builtin_eng);
+        dict.insert_native("errkind", "( err -- kind )", "The kind of an error caught by try, as a quoted word.",
+                           Calc::builtin_errkind);
+        dict.insert_native("error",  "( x kind -- err )", "An error value of the given kind carrying x, as caught by try.",
+                           Calc::builtin_error);
+        dict.insert_native("errval", "( err -- x )", "The value thrown, or the message of a caught error.",
+                           Calc::builtin_errval);
+        dict.insert_native("exp",    "( x -- y )", "e raised to the power x.",
+                           |calc| calc.perform_unary(f64::exp));
+        dict.insert_native("explode", "( vec -- ... )", "Push the elements of a vector on the stack.",
+                           Calc::builtin_explode);
+        dict.insert_native("exponential", "( rate -- x )", "A random number from the exponential distribution with the given rate.",
+                           Calc::builtin_exponential);
+        dict.insert_native("false",  "( -- bool )", "The boolean false.",
+                           |calc| { calc.data.push(Value::Bool(false)); Ok(()) });
+        dict.insert_native("filter", "( seq block -- seq )", "Keep the elements for which the block leaves a true value.",
+                           Calc::builtin_filter);
+        dict.insert_native("find",   "( seq block -- x )", "The first element for which the block leaves a true value, or undef.",
+                           Calc::builtin_find);
+        dict.insert_native("first",  "( seq -- x )", "The first element.",
+                           Calc::builtin_first);
+        dict.insert_native("fix",    "( n -- )", "Print numbers with n decimals, ints included.",
+                           Calc::builtin_fix);
+        dict.insert_native("flatten", "( vec -- vec )", "Replace vector elements by their elements.",
+                           Calc::builtin_flatten);
+        dict.insert_native("fold",   "( seq init block -- x )", "Combine the elements using the block, starting from init.",
+                           Calc::builtin_fold);
+        dict.insert_native("fold1",  "( seq block -- x )", "Like fold, using the first element as the initial value.",
+                           Calc::builtin_fold1);
+        dict.insert_native("format", "( format x... -- text )", "Format values with printf-style directives, e.g. %.2f, %5d or %#x.",
+                           Calc::builtin_format);
+        dict.insert_native("fromjson", "( text -- x )", "Parse JSON text. Strings become quoted words, objects vectors of [ key value ] pairs.",
+                           Calc::builtin_fromjson);
+        dict.insert_native("fv",     "( rate n pmt -- fv )", "Future value of n payments at the given rate per period.",
+                           Calc::builtin_fv);
+        dict.insert_native("grad",   "( -- )", "Take and give angles in gradians, 400 to a full turn.",
+                           Calc::builtin_grad);
+        dict.insert_native("group",  "( vec block -- groups )", "Group elements by the key the block computes, as [ key vec ] pairs.",
+                           Calc::builtin_group);
+        dict.insert_native("grouping", "( flag -- )", "Separate thousands with commas when printing numbers.",
+                           Calc::builtin_grouping);
+        dict.insert_io_native("help",   "( name -- )", "Print the documentation of a word.",
+                              Calc::builtin_help);
+        dict.insert_native("identity", "( n -- m )", "The n by n identity matrix.",
+                           Calc::builtin_identity);
+        dict.insert_native("if",     "( test then else -- ... )", "Run then if test is non-zero, otherwise else.",
+                           Calc::buildin_if);
+        dict.insert_native("indexof", "( seq x -- i )", "Index of the first element equal to x, or -1.",
+                           Calc::builtin_indexof);
+        dict.insert_native("integrate", "( a b block -- x )", "Integral of the block from a to b, by adaptive Simpson's rule.",
+                           Calc::builtin_integrate);
+        dict.insert_native("inv",    "( m -- m )", "Inverse of a square matrix, exact for ints and rationals.",
+                           Calc::builtin_inv);
+        dict.insert_native("irr",    "( flows -- rate )", "Internal rate of return of a vector of cash flows, one per period.",
+                           Calc::builtin_irr);
+        dict.insert_native("iterate", "( x block -- seq )", "The infinite sequence x, block applied to x, block applied to that, and so on.",
+                           Calc::builtin_iterate);
+        dict.insert_native("last",   "( vec -- x )", "The last element.",
+                           Calc::builtin_last);
+        dict.insert_native("len",    "( seq -- n )", "Number of elements in a vector or sequence.",
+                           Calc::builtin_length);
+        dict.insert_io_native("lines",  "( -- seq )", "The lines of standard input, read as the sequence is consumed.",
+                              Calc::builtin_lines);
+        dict.insert_native("ln",     "( x -- y )", "Natural logarithm.",
+                           |calc| calc.perform_unary(f64::ln));
+        dict.insert_io_native("load",   "( path -- )", "Evaluate a file, e.g. a session written by save.",
+                              Calc::builtin_load);
+        dict.insert_native("log",    "( x base -- y )", "Logarithm of x in the given base.",
+                           |calc| calc.perform_float_binary(f64::log));
+        dict.insert_native("map",    "( seq block -- seq )", "Apply the block to each element.",
+                           Calc::builtin_map);
+        dict.insert_native("matmul", "( a b -- c )", "Matrix product; b may be a vector, giving a vector.",
+                           Calc::builtin_matmul);
+        dict.insert_native("max",    "( x y -- z )", "The larger of two integers.",
+                           Calc::builtin_max);
+        dict.insert_native("min",    "( x y -- z )", "The smaller of two integers.",
+                           Calc::builtin_min);
+        dict.insert_native("minimize", "( a b block -- x )", "Where the block has a minimum between a and b, by golden-section search.",
+                           Calc::builtin_minimize);
+        dict.insert_native("mod",    "( x y -- z )", "Remainder of integer division, element-wise for vectors.",
+                           Calc::builtin_mod);
+        dict.insert_native("mul",    "( x y -- z )", "Multiply two numbers, element-wise for vectors.",
+                           |calc| calc.perform_binop(Mul::mul, Mul::mul, Mul::mul, datetime::mul));
+        dict.insert_native("norm",   "( vec -- x )", "Euclidean length of a vector.",
+                           Calc::builtin_norm);
+        dict.insert_native("normal", "( mean sd -- x )", "A random number from the normal distribution.",
+                           Calc::builtin_normal);
+        dict.insert_native("now",    "( -- date )", "The current date and time, in UTC.",
+                           Calc::builtin_now);
+        dict.insert_native("nper",   "( rate pmt pv -- n )", "Number of payments needed to pay off a loan.",
+                           Calc::builtin_nper);
+        dict.insert_native("npv",    "( rate flows -- x )", "Net present value of cash flows, the first one being now.",
+                           Calc::builtin_npv);
+        dict.insert_native("nth",    "( vec i -- x )", "The element at index i, counting from 0. Negative indices count from the end.",
+                           Calc::builtin_nth);
+        dict.insert_native("over",   "( x y -- x y x )", "Copy the second value to the top.",
+                           Calc::builtin_over);
+        dict.insert_native("pack",   "( ... n -- vec )", "Collect the top n values into a vector.",
+                           Calc::builtin_pack);
+        dict.insert_native("pmt",    "( rate n pv -- pmt )", "Payment per period paying off a loan in n periods.",
+                           Calc::builtin_pmt);
+        dict.insert_native("poisson", "( mean -- n )", "A random integer from the Poisson distribution.",
+                           Calc::builtin_poisson);
+        dict.insert_native("polyadd", "( p q -- r )", "Sum of two polynomials, given as coefficients with the highest power first.",
+                           Calc::builtin_polyadd);
+        dict.insert_native("polyder", "( p -- q )", "Derivative of a polynomial.",
+                           Calc::builtin_polyder);
+        dict.insert_native("polyfit", "( xs ys n -- p )", "Least-squares fit of a polynomial of degree n to the points.",
+                           Calc::builtin_polyfit);
+        dict.insert_native("polyint", "( p -- q )", "Integral of a polynomial, with a constant term of 0.",
+                           Calc::builtin_polyint);
+        dict.insert_native("polymul", "( p q -- r )", "Product of two polynomials.",
+                           Calc::builtin_polymul);
+        dict.insert_native("polyroots", "( p -- roots )", "All roots of a polynomial, as [ re im ] pairs.",
+                           Calc::builtin_polyroots);
+        dict.insert_native("polyval", "( p x -- y )", "Value of a polynomial at x.",
+                           Calc::builtin_polyval);
+        dict.insert_native("pop",    "( x -- )", "Discard the top value.",
+                           Calc::builtin_pop);
+        dict.insert_native("pop-back", "( vec -- vec x )", "Remove the last element.",
+                           Calc::builtin_pop_back);
+        dict.insert_native("pow",    "( x y -- z )", "x raised to the power y, element-wise for vectors.",
+                           Calc::builtin_pow);
+        dict.insert_io_native("print",  "( x -- )", "Print and discard the top value.",
+                              Calc::builtin_print);
+        dict.insert_native("push",   "( vec x -- vec )", "Append x to the vector.",
+                           Calc::builtin_push);
+        dict.insert_native("pv",     "( rate n pmt -- pv )", "Present value of n payments at the given rate per period.",
+                           Calc::builtin_pv);
+        dict.insert_native("rad",    "( -- )", "Take and give angles in radians, the default.",
+                           Calc::builtin_rad);
+        dict.insert_native("rand",   "( -- x )", "A random float, uniformly distributed from 0 up to 1.",
+                           Calc::builtin_rand);
+        dict.insert_native("randint", "( lo hi -- n )", "A random integer from lo to hi, inclusive.",
+                           Calc::builtin_randint);
+        dict.insert_native("range",  "( from to -- seq )", "The integers from from to to, inclusive.",
+                           Calc::builtin_range);
+        dict.insert_native("rate",   "( n pmt pv -- rate )", "Rate per period at which n payments pay off a loan.",
+                           Calc::builtin_rate);
+        dict.insert_native("repeat", "( ... block n -- ... )", "Run the block n times.",
+                           Calc::builtin_repeat);
+        dict.insert_native("reverse", "( vec -- vec )", "The elements in reverse order.",
+                           Calc::builtin_reverse);
+        dict.insert_native("roll3",  "( x y z -- y x z )", "Swap the second and third values.",
+                           Calc::builtin_roll3);
+        dict.insert_native("root",   "( a b block -- x )", "Where the block is zero between a and b, whose values must differ in sign.",
+                           Calc::builtin_root);
+        dict.insert_native("sample", "( vec n -- vec )", "n different elements of a vector, in random order.",
+                           Calc::builtin_sample);
+        dict.insert_io_native("save",   "( path -- )", "Write the stack and the words defined in this session to a file.",
+                              Calc::builtin_save);
+        dict.insert_native("sci",    "( n -- )", "Print floats in scientific notation with n decimals.",
+                           Calc::builtin_sci);
+        dict.insert_native("seconds", "( duration -- n )", "Number of seconds in a duration, or since 1970-01-01 for a date.",
+                           Calc::builtin_seconds);
+        dict.insert_native("seed",   "( n -- )", "Restart the random number generator from a seed, for reproducible results.",
+                           Calc::builtin_seed);
+        dict.insert_native("showlen", "( flag -- )", "Print the length after vectors.",
+                           Calc::builtin_showlen);
+        dict.insert_native("shuffle", "( vec -- vec )", "The elements of a vector in random order.",
+                           Calc::builtin_shuffle);
+        dict.insert_native("sign",   "( x -- n )", "-1, 0 or 1 according to the sign of x.",
+                           Calc::builtin_sign);
+        dict.insert_native("sin",    "( x -- y )", "Sine of the angle x.",
+                           |calc| calc.perform_trig(f64::sin));
+        dict.insert_native("sinh",   "( x -- y )", "Hyperbolic sine.",
+                           |calc| calc.perform_unary(f64::sinh));
+        dict.insert_native("slice",  "( vec from to -- vec )", "The elements from index from up to, not including, index to.",
+                           Calc::builtin_slice);
+        dict.insert_native("solve",  "( a b -- x )", "Solve a x = b for x, where b is a vector or a matrix.",
+                           Calc::builtin_solve);
+        dict.insert_native("sort",   "( vec -- vec )", "Sort numbers by value, quoted words alphabetically and vectors element by element.",
+                           Calc::builtin_sort);
+        dict.insert_native("sortby", "( vec block -- vec )", "Sort by the key the block computes, keeping the order of equal keys.",
+                           Calc::builtin_sortby);
+        dict.insert_native("sqrt",   "( x -- y )", "Square root.",
+                           |calc| calc.perform_unary(f64::sqrt));
+        dict.insert_native("std",    "( -- )", "Print numbers in the shortest form that reads back the same, the default.",
+                           Calc::builtin_std);
+        dict.insert_io_native("stdin",  "( -- vec )", "Read one number per line from standard input.",
+                              Calc::builtin_stdin);
+        dict.insert_native("strftime", "( date format -- text )", "Format a date, e.g. with ,%Y-%m-%d or ,%H:%M.",
+                           Calc::builtin_strftime);
+        dict.insert_native("sub",    "( x y -- z )", "Subtract y from x, element-wise for vectors.",
+                           |calc| calc.perform_binop(Sub::sub, Sub::sub, Sub::sub, datetime::sub));
+        dict.insert_native("swap",   "( x y -- y x )", "Swap the top two values.",
+                           Calc::builtin_swap);
+        dict.insert_native("take",   "( seq n -- seq )", "The first n elements.",
+                           Calc::builtin_take);
+        dict.insert_native("takewhile", "( seq block -- seq )", "The elements up to the first for which the block leaves a false value.",
+                           Calc::builtin_takewhile);
+        dict.insert_native("tan",    "( x -- y )", "Tangent of the angle x.",
+                           |calc| calc.perform_trig(f64::tan));
+        dict.insert_native("tanh",   "( x -- y )", "Hyperbolic tangent.",
+                           |calc| calc.perform_unary(f64::tanh));
+        dict.insert_native("throw",  "( x -- )", "Raise an error carrying x, to be caught by try.",
+                           Calc::builtin_throw);
+        dict.insert_native("today",  "( -- date )", "The current date, in UTC.",
+                           Calc::builtin_today);
+        dict.insert_native("tojson", "( x -- text )", "Format a value as JSON text.",
+                           Calc::builtin_tojson);
+        dict.insert_native("transpose", "( m -- m )", "Swap the rows and columns of a matrix.",
+                           Calc::builtin_transpose);
+        dict.insert_native("true",   "( -- bool )", "The boolean true.",
+                           |calc| { calc.data.push(Value::Bool(true)); Ok(()) });
+        dict.insert_native("try",    "( body handler -- ... )", "Run body; on error restore the stack, push the error and run handler. Limits and interrupts are not caught.",
+                           Calc::builtin_try);
+        dict.insert_native("undef",  "( -- undef )", "The undefined value.",
+                           |calc| { calc.data.push(Value::Undef); Ok(()) });
+        dict.insert_native("uniq",   "( vec -- vec )", "Remove repeated elements, keeping the first occurrence.",
+                           Calc::builtin_uniq);
+        dict.insert_native("weekday", "( date -- n )", "Day of the week, 1 for Monday to 7 for Sunday.",
+                           Calc::builtin_weekday);
+        dict.insert_native("workdays", "( from to -- n )", "Number of days from Monday to Friday from one date up to another.",
+                           Calc::builtin_workdays);
+        dict.insert_native("zip",    "( vec vec -- vec )", "Pair up elements of two vectors, as long as the shorter one.",
+                           Calc::builtin_zip);
         dict
     }
 }
@@ -158,40 +534,74 @@ impl Default for Dictionary {
 #[cfg(test)]
 mod tests {
     use std::rc::Rc;
-    use words::{BuiltinWord, Operation};
+    use words::{Operation, Value};
     use super::*;
 
+    fn is_native(op: &Option<Rc<Operation>>, name: &str) -> bool {
+        match op.as_ref().map(|op| &**op) {
+            Some(&Operation::Native(ref native)) => native.name == name,
+            _ => false,
+        }
+    }
+
     #[test]
     fn test() {
         let mut dict: Dictionary = Default::default();
-        assert_eq!(dict.lookup("add"),
-                   Some(Rc::new(Operation::Builtin(BuiltinWord::Add))));
+        let add = dict.lookup("add");
+        assert!(is_native(&add, "add"));
         assert_eq!(dict.lookup("plus"), None);
         dict.insert_alias("plus", "add");
-        assert_eq!(dict.lookup("plus"),
-                   Some(Rc::new(Operation::Builtin(BuiltinWord::Add))));
+        assert_eq!(dict.lookup("plus"), add);
         dict.insert("incr",
-                    Operation::Block(vec!["1", "+"].into_iter().map(|s| s.to_owned()).collect()));
+                    Operation::Value(Value::Block(vec!["1", "+"].into_iter().map(|s| s.to_owned()).collect())));
         assert_eq!(dict.lookup("incr"),
-                   Some(Rc::new(Operation::Block(vec!["1", "+"]
-                                                     .into_iter()
-                                                     .map(|s| s.to_string())
-                                                     .collect()))));
+                   Some(Rc::new(Operation::Value(Value::Block(vec!["1", "+"]
+                                                                  .into_iter()
+                                                                  .map(|s| s.to_string())
+                                                                  .collect())))));
+    }
+
+    #[test]
+    fn test_error_words() {
+        let dict: Dictionary = Default::default();
+        for name in &["try", "throw", "errkind", "errval"] {
+            assert!(is_native(&dict.lookup(name), name), "{}", name);
+            assert!(dict.lookup_doc(name).and_then(|doc| doc.signature).is_some(), "{}", name);
+        }
     }
 
     #[test]
     fn test_parent() {
         let mut dict: Dictionary = Default::default();
+        let add = dict.lookup("add");
         dict.insert_alias("plus", "add");
-        assert_eq!(dict.lookup("plus"),
-                   Some(Rc::new(Operation::Builtin(BuiltinWord::Add))));
+        assert_eq!(dict.lookup("plus"), add);
 
         let mut sub = Dictionary::with_parent(&dict);
-        assert_eq!(sub.lookup("plus"),
-                   Some(Rc::new(Operation::Builtin(BuiltinWord::Add))));
+        assert_eq!(sub.lookup("plus"), add);
 
         sub.insert_alias("+", "plus");
-        assert_eq!(sub.lookup("+"),
-                   Some(Rc::new(Operation::Builtin(BuiltinWord::Add))));
+        assert_eq!(sub.lookup("+"), add);
+    }
+
+    #[test]
+    fn test_doc() {
+        let mut dict: Dictionary = Default::default();
+        dict.insert_alias("plus", "add");
+        assert_eq!(dict.lookup_doc("plus").and_then(|doc| doc.signature),
+                   Some("( x y -- z )".to_owned()));
+
+        let mut sub = Dictionary::with_parent(&dict);
+        sub.set_doc("plus", Doc::parse("( a b -- c ) Sum of a and b"));
+        assert_eq!(sub.lookup_doc("plus"),
+                   Some(Doc {
+                       signature: Some("( a b -- c )".to_owned()),
+                       text: "Sum of a and b".to_owned(),
+                   }));
+        assert_eq!(sub.resolve("plus"), Some("add".to_owned()));
+
+        sub.insert("plus", Operation::Value(Value::Block(vec![])));
+        assert_eq!(sub.lookup_doc("plus"), None);
+        assert_eq!(Doc::parse("no signature").signature, None);
     }
 }
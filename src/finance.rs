@@ -0,0 +1,276 @@
+//! Builtin words for loans, annuities and cash flows.
+//!
+//! Rates are per period, e.g. `5/1200` for 5% a year paid monthly, and
+//! payments are made at the end of each period. The arithmetic is exact:
+//! floats are taken at their decimal value, so `0.1` is one tenth. Amounts
+//! of money are exact and rounded to cents, so that they add up; `2 fix`
+//! prints them as such.
+
+use num::{self, BigInt, One, ToPrimitive, Zero};
+use num::rational::BigRational;
+
+use calc::{float_cast, Calc};
+use errors::*;
+use numeric;
+use words::Value;
+
+/// The exact value of the decimal representation of `x`.
+fn decimal(x: f64) -> Option<BigRational> {
+    if !x.is_finite() {
+        return None;
+    }
+    let text = x.to_string();
+    let (int, frac) = match text.find('.') {
+        Some(dot) => (&text[..dot], &text[dot + 1..]),
+        None => (&text[..], ""),
+    };
+    let numer = BigInt::parse_bytes(format!("{}{}", int, frac).as_bytes(), 10)?;
+    Some(BigRational::new(numer, num::pow(BigInt::from(10), frac.len())))
+}
+
+fn cents(x: &BigRational) -> BigRational {
+    let hundred = BigRational::from_integer(BigInt::from(100));
+    (x * &hundred).round() / hundred
+}
+
+fn to_f64(x: &BigRational) -> f64 {
+    float_cast(Value::from_rational(x.clone())).unwrap_or(::std::f64::NAN)
+}
+
+/// An amount of money, rounded to cents.
+fn money(x: &BigRational) -> Value {
+    Value::from_rational(cents(x))
+}
+
+/// Number of periods up to which interest is compounded exactly. Beyond,
+/// the exact powers get too large to compute in reasonable time.
+const MAX_EXACT_PERIODS: usize = 1200;
+
+/// `x` to the power `n`, through floats for more than `MAX_EXACT_PERIODS`.
+fn power(x: &BigRational, n: usize) -> Result<BigRational> {
+    if n <= MAX_EXACT_PERIODS {
+        return Ok(num::pow(x.clone(), n));
+    }
+    decimal(to_f64(x).powf(n as f64)).ok_or_else(|| format!("compounding over {} periods overflows", n).into())
+}
+
+/// Operands of a finance word.
+struct Operands<'a> {
+    calc: &'a mut Calc,
+}
+
+impl<'a> Operands<'a> {
+    fn new(calc: &'a mut Calc) -> Operands<'a> {
+        Operands { calc: calc }
+    }
+
+    fn exact(&mut self, value: Value) -> Result<BigRational> {
+        if let Some(r) = value.as_rational() {
+            return Ok(r);
+        }
+        if let Value::Float(x) = value {
+            if let Some(r) = decimal(x) {
+                return Ok(r);
+            }
+        }
+        Err(ErrorKind::WrongTypeOperand(value, "number").into())
+    }
+
+    fn number(&mut self) -> Result<BigRational> {
+        let value = self.calc.get_operand()?;
+        self.exact(value)
+    }
+
+    fn numbers(&mut self) -> Result<Vec<BigRational>> {
+        let vec = self.calc.get_vector()?;
+        vec.into_iter().map(|value| self.exact(value)).collect()
+    }
+
+    /// A rate per period, which must be above -100%.
+    fn rate(&mut self) -> Result<BigRational> {
+        let value = self.calc.get_operand()?;
+        let rate = self.exact(value.clone())?;
+        if rate <= -BigRational::one() {
+            return Err(ErrorKind::WrongTypeOperand(value, "rate above -1").into());
+        }
+        Ok(rate)
+    }
+
+    fn periods(&mut self) -> Result<usize> {
+        let n = self.calc.get_int()?;
+        n.to_usize().ok_or_else(|| ErrorKind::WrongTypeOperand(Value::Int(n), "number of periods").into())
+    }
+}
+
+/// Present value of a cash flow of 1 at the end of each of `n` periods.
+fn annuity(rate: &BigRational, n: usize) -> Result<BigRational> {
+    if rate.is_zero() {
+        return Ok(BigRational::from_integer(BigInt::from(n)));
+    }
+    let one = BigRational::one();
+    let discount = power(&(&one / (&one + rate)), n)?;
+    Ok((&one - discount) / rate)
+}
+
+fn npv(rate: &BigRational, flows: &[BigRational]) -> BigRational {
+    let factor = BigRational::one() / (BigRational::one() + rate);
+    let mut discount = BigRational::one();
+    let mut sum = BigRational::zero();
+    for flow in flows {
+        sum = sum + flow * &discount;
+        discount = discount * &factor;
+    }
+    sum
+}
+
+/// The rate between -99% and 1000% per period at which `f` is zero.
+fn find_rate<F>(f: F) -> Result<f64>
+    where F: Fn(f64) -> f64
+{
+    numeric::root(&|r| Ok(f(r)), -0.99, 10.0)
+}
+
+impl Calc {
+    pub fn builtin_npv(&mut self) -> Result<()> {
+        let mut ops = Operands::new(self);
+        let flows = ops.numbers()?;
+        let rate = ops.rate()?;
+        let value = money(&npv(&rate, &flows));
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_irr(&mut self) -> Result<()> {
+        let flows: Vec<f64> = self.get_vector()?.into_iter().map(float_cast).collect::<Result<_>>()?;
+        let rate = find_rate(|r| flows.iter().rev().fold(0.0, |sum, flow| sum / (1.0 + r) + flow))
+            .chain_err(|| "no internal rate of return between -99% and 1000%")?;
+        self.data.push(Value::Float(rate));
+        Ok(())
+    }
+
+    pub fn builtin_pmt(&mut self) -> Result<()> {
+        let mut ops = Operands::new(self);
+        let pv = ops.number()?;
+        let n = ops.periods()?;
+        let rate = ops.rate()?;
+        if n == 0 {
+            return Err(ErrorKind::WrongTypeOperand(Value::Int(BigInt::zero()), "number of periods").into());
+        }
+        let value = money(&(pv / annuity(&rate, n)?));
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_pv(&mut self) -> Result<()> {
+        let mut ops = Operands::new(self);
+        let pmt = ops.number()?;
+        let n = ops.periods()?;
+        let rate = ops.rate()?;
+        let value = money(&(pmt * annuity(&rate, n)?));
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_fv(&mut self) -> Result<()> {
+        let mut ops = Operands::new(self);
+        let pmt = ops.number()?;
+        let n = ops.periods()?;
+        let rate = ops.rate()?;
+        let growth = power(&(BigRational::one() + &rate), n)?;
+        let value = money(&(pmt * annuity(&rate, n)? * growth));
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_nper(&mut self) -> Result<()> {
+        let pv = self.get_float_cast()?;
+        let pmt = self.get_float_cast()?;
+        let rate = self.get_float_cast()?;
+        let n = if rate == 0.0 {
+            pv / pmt
+        } else {
+            -(1.0 - rate * pv / pmt).ln() / (1.0 + rate).ln()
+        };
+        if !n.is_finite() || n < 0.0 {
+            bail!("a payment of {} never pays off {} at a rate of {}", pmt, pv, rate);
+        }
+        self.data.push(Value::Float(n));
+        Ok(())
+    }
+
+    pub fn builtin_rate(&mut self) -> Result<()> {
+        let pv = self.get_float_cast()?;
+        let pmt = self.get_float_cast()?;
+        let n = self.get_int()?.to_i32().ok_or(ErrorKind::BigIntTooLarge)?;
+        let rate = find_rate(|r| {
+            let annuity = if r.abs() < 1e-12 { n as f64 } else { (1.0 - (1.0 + r).powi(-n)) / r };
+            pmt * annuity - pv
+        }).chain_err(|| format!("no rate between -99% and 1000% pays off {} with {} payments of {}", pv, n, pmt))?;
+        self.data.push(Value::Float(rate));
+        Ok(())
+    }
+
+    pub fn builtin_amortize(&mut self) -> Result<()> {
+        let mut ops = Operands::new(self);
+        let pv = ops.number()?;
+        let n = ops.periods()?;
+        let rate = ops.rate()?;
+        ops.calc.check_vector_len(n)?;
+        if n == 0 {
+            return Err(ErrorKind::WrongTypeOperand(Value::Int(BigInt::zero()), "number of periods").into());
+        }
+        let payment = cents(&(&pv / annuity(&rate, n)?));
+        let mut balance = cents(&pv);
+        let mut rows = Vec::with_capacity(n);
+        for period in 1..n + 1 {
+            let interest = cents(&(&balance * &rate));
+            let principal = if period == n { balance.clone() } else { &payment - &interest };
+            balance = &balance - &principal;
+            rows.push(Value::Vector(vec![
+                Value::Int(BigInt::from(period)),
+                money(&interest),
+                money(&principal),
+                money(&balance),
+            ]));
+        }
+        self.data.push(Value::Vector(rows));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testing::{assert_cases, assert_errors, eval_float};
+
+    #[test]
+    fn test_finance() {
+        assert_cases(&[
+            ("1/10 [ -100 60 60 ] npv", "413/100"),
+            ("0.1 [ -100 60 60 ] npv", "413/100"),
+            ("0 12 1200 pmt", "100"),
+            ("5/1200 360 200000 pmt", "107364/100"),
+            ("0.05 10 100 fv", "125779/100"),
+            ("0.05 10 100 pv", "77217/100"),
+            ("0 3 300 amortize", "[ [ 1 0 100 200 ] [ 2 0 100 100 ] [ 3 0 100 0 ] ]"),
+            ("0.05 10000000 100 pv 0.05 1201 100 pv", "2000 2000"),
+            ("0.1 2 100 amortize", "[ [ 1 10 4762/100 5238/100 ] [ 2 524/100 5238/100 0 ] ]"),
+            ("0.1 2 100 amortize { 1 nth } map sum", "1524/100"),
+        ]);
+
+        let cases = [
+            ("[ -100 60 60 ] irr", 0.13066238629),
+            ("0.05 100 772.1734929 nper", 10.0),
+            ("10 100 772.1734929 rate", 0.05),
+        ];
+        for &(source, expected) in &cases {
+            let x = eval_float(source);
+            assert!((x - expected).abs() < 1e-5, "{}: {} != {}", source, x, expected);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_errors(&["[ 100 60 ] irr", "0.1 10 1000 nper", "0.05 0 100 pmt", "0.05 -1 100 pv", "-1 2 100 pmt",
+                        "0.05 10000000 100 fv"]);
+    }
+}
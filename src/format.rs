@@ -0,0 +1,486 @@
+//! How numbers and vectors are printed.
+//!
+//! The format can be set with words, or in the config file
+//! `~/.config/pnc/config` with lines like these:
+//!
+//! ```text
+//! # two decimals, with thousands separators
+//! notation = fix 2
+//! grouping = true
+//! vector-length = false
+//! ```
+//!
+//! The `format` word formats values into text with printf-style directives:
+//! `%[flags][width][.precision]conversion`, where the flags are `-` (align
+//! left), `0` (pad numbers with zeros), `+` and space (sign of positive
+//! numbers) and `#` (radix prefix), and the conversions are
+//!
+//! - `d` an int, and `x`, `X`, `o` and `b` an int in hex, octal or binary
+//! - `f` a number with a fixed number of decimals, 6 by default, exact for
+//!   ints and rationals
+//! - `e` a number in scientific notation
+//! - `s` any value as printed, cut to the precision if given
+//!
+//! and `%%` is a percent sign.
+
+use num::{BigInt, Signed, ToPrimitive};
+use num::rational::BigRational;
+
+use std::iter;
+
+use calc::{float_cast, Calc};
+use errors::*;
+use words::Value;
+
+/// How floats, and rationals other than in `Std`, are written. Ints are
+/// written in full, with the decimals of `Fix` added.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// The shortest form which reads back as the same number.
+    Std,
+    /// A fixed number of decimals.
+    Fix(usize),
+    /// One digit before the point and an exponent, e.g. `1.50e3`.
+    Sci(usize),
+    /// An exponent which is a multiple of 3, e.g. `15.0e3`.
+    Eng(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub notation: Notation,
+    /// Separate thousands with commas.
+    pub grouping: bool,
+    /// Follow vectors by their length.
+    pub vector_length: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> NumberFormat {
+        NumberFormat { notation: Notation::Std, grouping: false, vector_length: true }
+    }
+}
+
+/// Insert commas between groups of three digits of the integer part of a
+/// formatted number.
+fn group(number: &str) -> String {
+    let digits_start = number.find(|c: char| c.is_ascii_digit()).unwrap_or(number.len());
+    let digits_end = number[digits_start..].find(|c: char| !c.is_ascii_digit())
+        .map_or(number.len(), |i| i + digits_start);
+    let digits = &number[digits_start..digits_end];
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    format!("{}{}{}", &number[..digits_start], grouped, &number[digits_end..])
+}
+
+/// `r` with `decimals` digits after the point, rounded half away from zero.
+fn fixed_rational(r: &BigRational, decimals: usize) -> String {
+    let scale = ::num::pow(BigInt::from(10), decimals);
+    let scaled = (r * BigRational::from_integer(scale.clone())).round().to_integer();
+    let digits = scaled.abs().to_string();
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (int, frac) = digits.split_at(digits.len() - decimals);
+    let sign = if scaled.is_negative() { "-" } else { "" };
+    if decimals == 0 {
+        format!("{}{}", sign, int)
+    } else {
+        format!("{}{}.{}", sign, int, frac)
+    }
+}
+
+fn engineering(x: f64, decimals: usize) -> String {
+    if x == 0.0 || !x.is_finite() {
+        return format!("{:.*}e0", decimals, x);
+    }
+    let mut exponent = (x.abs().log10() / 3.0).floor() as i32 * 3;
+    let mut mantissa = format!("{:.*}", decimals, x / 10f64.powi(exponent));
+    // Rounding may give a mantissa of 1000
+    if mantissa.trim_start_matches('-').starts_with("1000") {
+        exponent += 3;
+        mantissa = format!("{:.*}", decimals, x / 10f64.powi(exponent));
+    }
+    format!("{}e{}", mantissa, exponent)
+}
+
+impl NumberFormat {
+    fn float(&self, x: f64) -> String {
+        match self.notation {
+            Notation::Std => x.to_string(),
+            Notation::Fix(decimals) => format!("{:.*}", decimals, x),
+            Notation::Sci(decimals) => format!("{:.*e}", decimals, x),
+            Notation::Eng(decimals) => engineering(x, decimals),
+        }
+    }
+
+    fn number(&self, value: &Value) -> Option<String> {
+        let text = match *value {
+            Value::Int(ref i) => match self.notation {
+                Notation::Fix(decimals) if decimals > 0 => format!("{}.{}", i, "0".repeat(decimals)),
+                _ => i.to_string(),
+            },
+            Value::Float(x) => self.float(x),
+            Value::Rational(ref r) => match self.notation {
+                Notation::Std => r.to_string(),
+                Notation::Fix(decimals) => fixed_rational(r, decimals),
+                _ => self.float(value.as_float_cast().unwrap_or(::std::f64::NAN)),
+            },
+            _ => return None,
+        };
+        Some(if self.grouping { group(&text) } else { text })
+    }
+
+    /// `value` as printed on the stack.
+    pub fn value(&self, value: &Value) -> String {
+        if let Some(text) = self.number(value) {
+            return text;
+        }
+        match *value {
+            Value::Vector(ref vec) => {
+                let elems: Vec<String> = vec.iter().map(|v| self.value(v)).collect();
+                if self.vector_length {
+                    format!("[{}] len: {}", elems.join(", "), vec.len())
+                } else {
+                    format!("[{}]", elems.join(", "))
+                }
+            }
+            ref value => value.to_string(),
+        }
+    }
+
+    /// Apply the settings in a config file.
+    pub fn configure(&mut self, config: &str) -> Result<()> {
+        for (n, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let setting = parts.next().map(str::trim)
+                .ok_or_else(|| format!("line {}: expected 'key = value'", n + 1))?;
+            let invalid = || Error::from(format!("line {}: invalid value '{}' for {}", n + 1, setting, key));
+            match key {
+                "notation" => self.notation = parse_notation(setting).ok_or_else(invalid)?,
+                "grouping" => self.grouping = setting.parse().map_err(|_| invalid())?,
+                "vector-length" => self.vector_length = setting.parse().map_err(|_| invalid())?,
+                _ => bail!("line {}: unknown setting '{}'", n + 1, key),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A notation written as `std`, `fix N`, `sci N` or `eng N`.
+fn parse_notation(text: &str) -> Option<Notation> {
+    let mut words = text.split_whitespace();
+    let name = words.next()?;
+    if name == "std" {
+        return if words.next().is_none() { Some(Notation::Std) } else { None };
+    }
+    let decimals = words.next()?.parse().ok()?;
+    if words.next().is_some() {
+        return None;
+    }
+    match name {
+        "fix" => Some(Notation::Fix(decimals)),
+        "sci" => Some(Notation::Sci(decimals)),
+        "eng" => Some(Notation::Eng(decimals)),
+        _ => None,
+    }
+}
+
+/// Largest width or precision of a `format` directive.
+const MAX_WIDTH: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Directive {
+    left: bool,
+    zero: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    width: usize,
+    precision: Option<usize>,
+    conversion: char,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Piece {
+    Text(String),
+    Directive(Directive),
+}
+
+fn parse_format(format: &str) -> Result<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            text.push('%');
+            continue;
+        }
+        let mut directive = Directive::default();
+        while let Some(&flag) = chars.peek() {
+            match flag {
+                '-' => directive.left = true,
+                '0' => directive.zero = true,
+                '+' => directive.plus = true,
+                ' ' => directive.space = true,
+                '#' => directive.alternate = true,
+                _ => break,
+            }
+            chars.next();
+        }
+        let number = |chars: &mut iter::Peekable<::std::str::Chars>| -> Result<usize> {
+            let mut n = 0;
+            while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                chars.next();
+                n = n * 10 + digit as usize;
+                if n > MAX_WIDTH {
+                    bail!("width or precision above {} in format '{}'", MAX_WIDTH, format);
+                }
+            }
+            Ok(n)
+        };
+        directive.width = number(&mut chars)?;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            directive.precision = Some(number(&mut chars)?);
+        }
+        directive.conversion = match chars.next() {
+            Some(c) if "dxXobfes".contains(c) => c,
+            Some(c) => bail!("unknown conversion '%{}' in format '{}'", c, format),
+            None => bail!("incomplete directive at the end of format '{}'", format),
+        };
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text.split_off(0)));
+        }
+        pieces.push(Piece::Directive(directive));
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+fn directives(pieces: &[Piece]) -> usize {
+    pieces.iter().filter(|piece| match **piece { Piece::Directive(..) => true, _ => false }).count()
+}
+
+impl Directive {
+    fn format(&self, value: &Value, number_format: &NumberFormat) -> Result<String> {
+        if self.conversion == 's' {
+            let mut text = number_format.value(value);
+            if let Some(precision) = self.precision {
+                text = text.chars().take(precision).collect();
+            }
+            return Ok(self.pad("", "", &text));
+        }
+        let (number, prefix) = match self.conversion {
+            'f' => match value.as_rational() {
+                Some(r) => (fixed_rational(&r, self.precision.unwrap_or(6)), ""),
+                None => (format!("{:.*}", self.precision.unwrap_or(6), float_cast(value.clone())?), ""),
+            },
+            'e' => (format!("{:.*e}", self.precision.unwrap_or(6), float_cast(value.clone())?), ""),
+            radix => {
+                let i = match *value {
+                    Value::Int(ref i) => i,
+                    _ => return Err(ErrorKind::WrongTypeOperand(value.clone(), "int").into()),
+                };
+                match radix {
+                    'x' => (i.to_str_radix(16), "0x"),
+                    'X' => (i.to_str_radix(16).to_uppercase(), "0X"),
+                    'o' => (i.to_str_radix(8), "0o"),
+                    'b' => (i.to_str_radix(2), "0b"),
+                    _ => (i.to_string(), ""),
+                }
+            }
+        };
+        let (sign, digits) = if number.starts_with('-') {
+            ("-", &number[1..])
+        } else if self.plus {
+            ("+", &number[..])
+        } else if self.space {
+            (" ", &number[..])
+        } else {
+            ("", &number[..])
+        };
+        let prefix = if self.alternate { prefix } else { "" };
+        Ok(self.pad(sign, prefix, digits))
+    }
+
+    /// `sign`, `prefix` and `digits` padded to the width.
+    fn pad(&self, sign: &str, prefix: &str, digits: &str) -> String {
+        let len = sign.len() + prefix.len() + digits.chars().count();
+        let fill = self.width.saturating_sub(len);
+        if self.left {
+            format!("{}{}{}{}", sign, prefix, digits, " ".repeat(fill))
+        } else if self.zero && self.conversion != 's' && digits.starts_with(|c: char| c.is_ascii_digit()) {
+            format!("{}{}{}{}", sign, prefix, "0".repeat(fill), digits)
+        } else {
+            format!("{}{}{}{}", " ".repeat(fill), sign, prefix, digits)
+        }
+    }
+}
+
+impl Calc {
+    /// Find the format string of `format`: the nearest quoted word below the
+    /// top with a directive for each value above it. With no such word, the
+    /// top is the format string, and its values are missing.
+    fn get_format(&mut self) -> Result<(Vec<Piece>, Vec<Value>)> {
+        let n = self.data.len();
+        let found = (1..n).chain(iter::once(0)).filter(|_| n > 0).find(|&k| match self.data[n - 1 - k] {
+            Value::QuotedWord(ref word) => parse_format(word).ok().map_or(false, |p| directives(&p) == k),
+            _ => false,
+        });
+        match found {
+            Some(k) => {
+                let values = self.data.split_off(n - k);
+                let pieces = parse_format(&self.get_word()?)?;
+                Ok((pieces, values))
+            }
+            None => {
+                parse_format(&self.get_word()?)?;
+                Err(ErrorKind::MissingOperand.into())
+            }
+        }
+    }
+
+    pub fn builtin_format(&mut self) -> Result<()> {
+        let (pieces, values) = self.get_format()?;
+        let mut values = values.iter();
+        let mut text = String::new();
+        for piece in &pieces {
+            match *piece {
+                Piece::Text(ref t) => text.push_str(t),
+                Piece::Directive(ref d) => text.push_str(&d.format(values.next().unwrap(), &self.number_format)?),
+            }
+        }
+        self.data.push(Value::QuotedWord(text));
+        Ok(())
+    }
+
+    fn get_decimals(&mut self) -> Result<usize> {
+        let n = self.get_int()?;
+        match n.to_usize() {
+            Some(n) if n <= 100 => Ok(n),
+            _ => Err(ErrorKind::WrongTypeOperand(Value::Int(n), "number of decimals up to 100").into()),
+        }
+    }
+
+    fn get_flag(&mut self) -> Result<bool> {
+        match self.get_operand()? {
+            Value::Bool(b) => Ok(b),
+            Value::Int(ref i) => Ok(i.is_positive()),
+            v => Err(ErrorKind::WrongTypeOperand(v, "bool").into()),
+        }
+    }
+
+    pub fn builtin_std(&mut self) -> Result<()> {
+        self.number_format.notation = Notation::Std;
+        Ok(())
+    }
+
+    pub fn builtin_fix(&mut self) -> Result<()> {
+        self.number_format.notation = Notation::Fix(self.get_decimals()?);
+        Ok(())
+    }
+
+    pub fn builtin_sci(&mut self) -> Result<()> {
+        self.number_format.notation = Notation::Sci(self.get_decimals()?);
+        Ok(())
+    }
+
+    pub fn builtin_eng(&mut self) -> Result<()> {
+        self.number_format.notation = Notation::Eng(self.get_decimals()?);
+        Ok(())
+    }
+
+    pub fn builtin_grouping(&mut self) -> Result<()> {
+        self.number_format.grouping = self.get_flag()?;
+        Ok(())
+    }
+
+    pub fn builtin_showlen(&mut self) -> Result<()> {
+        self.number_format.vector_length = self.get_flag()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{assert_cases, assert_errors};
+
+    fn format(source: &str) -> String {
+        let mut calc = Calc::with_prelude().unwrap();
+        calc.eval(source).unwrap();
+        let value = calc.data.pop().unwrap();
+        calc.number_format.value(&value)
+    }
+
+    #[test]
+    fn test_format() {
+        let cases = [
+            ("1.5", "1.5"),
+            ("[ 1 2.5 ]", "[1, 2.5] len: 2"),
+            ("false showlen [ 1 [ 2 ] ]", "[1, [2]]"),
+            ("2 fix 3.14159", "3.14"),
+            ("2 fix 7", "7.00"),
+            ("2 fix 2/3", "0.67"),
+            ("2 fix 5/1200 360 200000 pmt", "1073.64"),
+            ("2 fix -1/200", "-0.01"),
+            ("0 fix 5/2", "3"),
+            ("true grouping 2 fix -1234567", "-1,234,567.00"),
+            ("2/3", "2/3"),
+            ("3 sci 1234.0", "1.234e3"),
+            ("2 eng 12346.0", "12.35e3"),
+            ("1 eng 0.000999999", "1.0e-3"),
+            ("2 fix 2 sci std 0.1", "0.1"),
+            ("true grouping 1234567", "1,234,567"),
+            ("true grouping 2 fix -1234567.891", "-1,234,567.89"),
+            ("true grouping 999.5", "999.5"),
+        ];
+        for &(source, expected) in &cases {
+            assert_eq!(format(source), expected, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_format_word() {
+        assert_cases(&[
+            (r#"",%.2f of %d" 3.14159 7 format"#, r#"",3.14 of 7""#),
+            (r#"",%d%% done" 50 format"#, r#"",50% done""#),
+            (r#"",[%5d|%-5d|%05d|%+d]" 42 42 -42 42 format"#, r#"",[   42|42   |-0042|+42]""#),
+            (r#"",%x %X %#o %#b" 255 255 8 -5 format"#, r#"",ff FF 0o10 -0b101""#),
+            (r#"",%08.3f" -3.14159 format"#, ",-003.142"),
+            (r#"",%f %.0f" 1/3 5/2 format"#, r#"",0.333333 3""#),
+            (r#"",%.2e" 12345 format"#, ",1.23e4"),
+            (r#"",%s=%6.3s|" ,name ,value format"#, r#"",name=   val|""#),
+            (r#"",%s" [ 1 2 ] format"#, r#"",[1, 2] len: 2""#),
+            (r#"",%s-%s" ,a ,%s format"#, ",a-%s"),
+            (r#"",plain" format"#, ",plain"),
+        ]);
+        assert_errors(&[r#"",%d" 1.5 format"#, r#"",%d %d" 1 format"#, r#"",%q" 1 format"#, r#"",%5" format"#, "1 format"]);
+    }
+
+    #[test]
+    fn test_configure() {
+        let mut format = NumberFormat::default();
+        format.configure("# reports\nnotation = fix 2\n\ngrouping = true\nvector-length=false\n").unwrap();
+        assert_eq!(format, NumberFormat { notation: Notation::Fix(2), grouping: true, vector_length: false });
+        assert!(format.configure("notation = fix").is_err());
+        assert!(format.configure("colour = red").is_err());
+        assert!(format.configure("grouping").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! Conversion between values and JSON.
+
+use num::{BigInt, ToPrimitive};
+use serde_json::{self, Value as Json};
+
+use errors::*;
+use words::Value;
+
+/// JSON representing `value`. Integers outside the 64-bit range become
+/// strings of digits, rationals become floats, and floats which are not
+/// finite become null.
+pub fn to_json(value: &Value) -> Json {
+    match *value {
+        Value::Undef => Json::Null,
+        Value::Bool(b) => Json::Bool(b),
+        Value::Int(ref i) => {
+            if let Some(n) = i.to_i64() {
+                Json::from(n)
+            } else if let Some(n) = i.to_u64() {
+                Json::from(n)
+            } else {
+                Json::String(i.to_string())
+            }
+        }
+        Value::Float(f) => Json::from(f),
+        Value::Rational(_) => Json::from(value.as_float_cast().unwrap_or(::std::f64::NAN)),
+        Value::Vector(ref vec) => Json::Array(vec.iter().map(to_json).collect()),
+        Value::Block(ref block) => Json::String(format!("{{ {} }}", block.join(" "))),
+        Value::QuotedWord(ref word) => Json::String(word.clone()),
+        Value::Seq(ref seq) => Json::String(seq.to_string()),
+        Value::DateTime(t) => Json::String(t.to_string()),
+        Value::Duration(d) => Json::String(d.to_string()),
+        Value::Error(ref kind, ref value) => {
+            let mut map = serde_json::Map::new();
+            map.insert("error".to_owned(), Json::String(kind.clone()));
+            map.insert("value".to_owned(), to_json(value));
+            Json::Object(map)
+        }
+    }
+}
+
+/// The value represented by `json`. Strings become quoted words and objects
+/// become vectors of `[ key value ]` pairs, sorted by key.
+pub fn from_json(json: &Json) -> Value {
+    match *json {
+        Json::Null => Value::Undef,
+        Json::Bool(b) => Value::Bool(b),
+        Json::Number(ref n) => {
+            if let Some(i) = n.as_i64() {
+                Value::Int(BigInt::from(i))
+            } else if let Some(i) = n.as_u64() {
+                Value::Int(BigInt::from(i))
+            } else {
+                Value::Float(n.as_f64().unwrap_or(::std::f64::NAN))
+            }
+        }
+        Json::String(ref s) => Value::QuotedWord(s.clone()),
+        Json::Array(ref vec) => Value::Vector(vec.iter().map(from_json).collect()),
+        Json::Object(ref map) => {
+            Value::Vector(map.iter()
+                .map(|(key, value)| Value::Vector(vec![Value::QuotedWord(key.clone()), from_json(value)]))
+                .collect())
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Value> {
+    let json: Json = serde_json::from_str(text)
+        .map_err(|e| Error::from(ErrorKind::InvalidJson(e.to_string())))?;
+    Ok(from_json(&json))
+}
+
+pub fn format(value: &Value) -> String {
+    to_json(value).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(i: i64) -> Value {
+        Value::Int(BigInt::from(i))
+    }
+
+    #[test]
+    fn test_from_json() {
+        assert_eq!(parse(r#"[1, 2.5, "a b", null, true, {"x": [], "a": -3}]"#).unwrap(),
+                   Value::Vector(vec![
+                       int(1),
+                       Value::Float(2.5),
+                       Value::QuotedWord("a b".to_owned()),
+                       Value::Undef,
+                       Value::Bool(true),
+                       Value::Vector(vec![
+                           Value::Vector(vec![Value::QuotedWord("a".to_owned()), int(-3)]),
+                           Value::Vector(vec![Value::QuotedWord("x".to_owned()), Value::Vector(vec![])]),
+                       ]),
+                   ]));
+        assert!(parse("[1,").is_err());
+    }
+
+    #[test]
+    fn test_to_json() {
+        let big = Value::parse("123456789012345678901234567890").unwrap();
+        let value = Value::Vector(vec![int(-1), Value::Float(0.5), big, Value::QuotedWord("w".to_owned())]);
+        assert_eq!(format(&value), r#"[-1,0.5,"123456789012345678901234567890","w"]"#);
+        assert_eq!(parse(&format(&value)).unwrap(), Value::Vector(vec![
+            int(-1), Value::Float(0.5), Value::QuotedWord("123456789012345678901234567890".to_owned()),
+            Value::QuotedWord("w".to_owned()),
+        ]));
+    }
+}
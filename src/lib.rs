@@ -0,0 +1,268 @@
+//! A postfix notation calculator.
+//!
+//! Besides the `pnc` command line tool, the calculator can be embedded as an
+//! expression language:
+//!
+//! ```
+//! let stack = pnc::eval("[ 1 2 3 ] sum 2 .").unwrap();
+//! assert_eq!(stack, vec![pnc::Value::parse("12").unwrap()]);
+//! ```
+//!
+//! Words implemented in Rust can be added with [`Calc::register`]:
+//!
+//! ```
+//! use pnc::{Calc, Value};
+//!
+//! let mut calc = Calc::with_prelude().unwrap();
+//! calc.register("half", "( x -- y ) Half of x", |calc| {
+//!     let x = calc.get_float_cast()?;
+//!     calc.data.push(Value::Float(x / 2.0));
+//!     Ok(())
+//! });
+//! calc.eval("[ 2 4 ] { half } map sum").unwrap();
+//! assert_eq!(calc.data, vec![Value::Float(3.0)]);
+//! ```
+
+#![allow(unknown_lints)]
+
+#[macro_use] extern crate log;
+extern crate shlex;
+#[macro_use]
+extern crate error_chain;
+extern crate num;
+extern crate serde_json;
+
+mod words;
+mod builtins;
+mod vectors;
+mod finance;
+mod matrix;
+mod numeric;
+mod poly;
+
+pub mod errors {
+    use words::Word;
+    error_chain! {
+
+        errors {
+            DivisionByZero {
+                description("division by zero")
+            }
+            MissingOperand {
+                description("operation needs an operand but stack is empty")
+            }
+            WrongTypeOperand(value: ::words::Value, expected: &'static str) {
+                description("operand has a wrong type")
+                display("operand has a wrong type, got operand '{}' (of type {}) but expected type {}", value, value.type_of(), expected)
+            }
+            BlockNoResult {
+                description("block left no result on the stack")
+            }
+            WordParseError(word: Word) {
+                description("could not parse word as number or operation")
+                display("could not parse word '{}' as number or operation", word)
+            }
+            BigIntTooLarge {
+                display("bigint is too large to convert to float")
+            }
+            UnknownWord(word: Word) {
+                display("the word '{}' does not exist", word)
+            }
+            Thrown(value: ::words::Value) {
+                description("error raised by throw")
+                display("uncaught error: {}", value)
+            }
+            AssertionFailed(message: String) {
+                description("assertion failed")
+                display("assertion failed: {}", message)
+            }
+            LimitExceeded(what: &'static str, limit: u64) {
+                description("resource limit exceeded")
+                display("{} limit of {} exceeded", what, limit)
+            }
+            Sandboxed(word: Word) {
+                description("word not available in sandbox mode")
+                display("the word '{}' is not available in sandbox mode", word)
+            }
+            Interrupted {
+                description("evaluation interrupted")
+            }
+            LengthMismatch(left: usize, right: usize) {
+                description("vectors have different lengths")
+                display("cannot combine vectors of lengths {} and {} element-wise", left, right)
+            }
+            IndexOutOfRange(index: i64, len: usize) {
+                description("index out of range")
+                display("index {} is out of range for a vector of length {}", index, len)
+            }
+            InvalidJson(message: String) {
+                description("invalid JSON")
+                display("invalid JSON: {}", message)
+            }
+            SingularMatrix {
+                description("matrix is singular")
+            }
+        }
+    }
+
+    impl ErrorKind {
+        /// Short name of the kind of error, as seen by a `try` handler.
+        pub fn name(&self) -> &'static str {
+            match *self {
+                ErrorKind::DivisionByZero => "division-by-zero",
+                ErrorKind::MissingOperand => "missing-operand",
+                ErrorKind::WrongTypeOperand(..) => "wrong-type-operand",
+                ErrorKind::BlockNoResult => "block-no-result",
+                ErrorKind::WordParseError(..) => "word-parse-error",
+                ErrorKind::BigIntTooLarge => "bigint-too-large",
+                ErrorKind::UnknownWord(..) => "unknown-word",
+                ErrorKind::Thrown(..) => "thrown",
+                ErrorKind::AssertionFailed(..) => "assertion-failed",
+                ErrorKind::LimitExceeded(..) => "limit-exceeded",
+                ErrorKind::Sandboxed(..) => "sandboxed",
+                ErrorKind::Interrupted => "interrupted",
+                ErrorKind::LengthMismatch(..) => "length-mismatch",
+                ErrorKind::IndexOutOfRange(..) => "index-out-of-range",
+                ErrorKind::InvalidJson(..) => "invalid-json",
+                ErrorKind::SingularMatrix => "singular-matrix",
+                _ => "error",
+            }
+        }
+    }
+
+    impl Error {
+        /// The innermost error in the chain, i.e. the one that caused all the others.
+        pub fn root(&self) -> &Error {
+            let mut err = self;
+            while let Some(next) = err.1.next_error.as_ref().and_then(|e| e.downcast_ref::<Error>()) {
+                err = next;
+            }
+            err
+        }
+    }
+}
+
+mod calc;
+mod check;
+mod csv;
+mod datetime;
+mod debug;
+mod dict;
+mod format;
+mod json;
+mod limits;
+mod rng;
+mod seq;
+mod session;
+mod stream;
+mod test_runner;
+#[cfg(test)]
+mod testing;
+
+pub use calc::Calc;
+pub use errors::{Error, ErrorKind, Result};
+pub use limits::Limits;
+pub use words::Value;
+
+/// What the `pnc` command line tool needs besides the API. This is not part
+/// of the API and may change at any time.
+#[doc(hidden)]
+pub mod cli {
+    pub use csv::{delimiter, read_table};
+    pub use stream::Each;
+    pub use test_runner::run_dir;
+}
+
+/// Source of the builtin prelude.
+pub const PRELUDE: &str = include_str!("../prelude.pnc");
+
+/// Evaluate `source` in a new calculator with the builtin prelude loaded and
+/// return the resulting stack.
+pub fn eval(source: &str) -> Result<Vec<Value>> {
+    let mut calc = Calc::with_prelude()?;
+    calc.eval(source)?;
+    Ok(calc.data)
+}
+
+/// Like [`eval`], but within the given resource limits. The prelude is
+/// loaded before the limits take effect.
+pub fn eval_with_limits(source: &str, limits: Limits) -> Result<Vec<Value>> {
+    let mut calc = Calc::with_prelude()?;
+    calc.set_limits(limits);
+    calc.eval(source)?;
+    Ok(calc.data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("1 2 +").unwrap(), vec![Value::parse("3").unwrap()]);
+        assert_eq!(eval("5 upto collect").unwrap(), eval("[ 1 2 3 4 5 ]").unwrap());
+        let mut calc = Calc::with_prelude().unwrap();
+        calc.eval("3 upto { 2 . } map [ 2 upto ] 1 { 1 + } iterate").unwrap();
+        let shown = calc.data.iter().map(|v| calc.collect_finite(v.clone()).unwrap()).collect::<Vec<_>>();
+        assert_eq!(&shown[..2], &eval("[ 2 4 6 ] [ [ 1 2 ] ]").unwrap()[..]);
+        assert_eq!(shown[2].to_string(), "<seq>");
+        assert!(eval("1 foo").is_err());
+
+        let int = |i| Value::parse(i).unwrap();
+        assert_eq!(eval("[ 1 [ 2 [ ] ] 3 ]").unwrap(),
+                   vec![Value::Vector(vec![int("1"), Value::Vector(vec![int("2"), Value::Vector(vec![])]), int("3")])]);
+    }
+
+    #[test]
+    fn test_register() {
+        let mut calc = Calc::with_prelude().unwrap();
+        calc.register("answer", "( -- x ) The answer", |calc| {
+            calc.data.push(Value::parse("42").unwrap());
+            Ok(())
+        });
+        calc.eval("answer ,answer? ,answer alias answer? +").unwrap();
+        assert_eq!(calc.data, vec![Value::parse("84").unwrap()]);
+        assert_eq!(calc.check(), vec![]);
+    }
+
+    #[test]
+    fn test_arg() {
+        assert_eq!(eval("1 2 [ arg 3 ]").unwrap(), eval("1 [ 2 3 ]").unwrap());
+        assert_eq!(eval(",take ,arg alias 1 [ take take ]").unwrap_err().root().kind().name(), "missing-operand");
+        assert_eq!(eval(",take ,arg alias 1 2 [ take take ]").unwrap(), eval("[ 2 1 ]").unwrap());
+
+        // A word named arg is executed like any other
+        let mut calc = Calc::with_prelude().unwrap();
+        calc.register("arg", "( -- x ) Not the builtin arg", |calc| {
+            calc.data.push(Value::parse("7").unwrap());
+            Ok(())
+        });
+        calc.eval("1 [ arg ]").unwrap();
+        assert_eq!(calc.data, eval("1 [ 7 ]").unwrap());
+    }
+
+    #[test]
+    fn test_limits() {
+        let limit_kind = |source: &str| {
+            eval_with_limits(source, Limits::sandboxed()).unwrap_err().root().kind().name()
+        };
+        assert_eq!(limit_kind("{ 1 } 100000000 repeat"), "limit-exceeded");
+        assert_eq!(limit_kind("{ } 100000000000 repeat"), "limit-exceeded");
+        assert_eq!(limit_kind("{ { 1 } 100000000 repeat } { errkind } try"), "limit-exceeded");
+        assert_eq!(limit_kind(",f { f } def f"), "limit-exceeded");
+        assert_eq!(limit_kind("1000000000 upto collect"), "limit-exceeded");
+        assert_eq!(limit_kind("100000 identity"), "limit-exceeded");
+        assert_eq!(limit_kind("stdin"), "sandboxed");
+        assert_eq!(eval_with_limits("{ 1 print } { errkind } try", Limits::sandboxed()).unwrap(),
+                   vec![Value::QuotedWord("sandboxed".to_owned())]);
+        assert!(eval_with_limits("10 upto sum", Limits::sandboxed()).is_ok());
+
+        let limits = Limits { instructions: Some(100_000), ..Limits::default() };
+        assert_eq!(eval_with_limits("1000000000 upto sum", limits).unwrap_err().root().kind().name(),
+                   "limit-exceeded");
+
+        let limits = Limits { int_bits: Some(64), ..Limits::default() };
+        assert_eq!(eval_with_limits("4294967296 dup .", limits).unwrap_err().root().kind().name(),
+                   "limit-exceeded");
+    }
+}
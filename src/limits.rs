@@ -0,0 +1,30 @@
+/// Bounds on the resources an evaluation may use. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Number of words executed.
+    pub instructions: Option<u64>,
+    /// Number of values on a stack.
+    pub stack_depth: Option<usize>,
+    /// Nesting of defined words being executed.
+    pub recursion_depth: Option<usize>,
+    /// Number of elements in a vector.
+    pub vector_length: Option<usize>,
+    /// Number of bits in an integer.
+    pub int_bits: Option<usize>,
+    /// Disable words that read or write files, the terminal or standard streams.
+    pub sandbox: bool,
+}
+
+impl Limits {
+    /// Limits suitable for evaluating untrusted input.
+    pub fn sandboxed() -> Limits {
+        Limits {
+            instructions: Some(10_000_000),
+            stack_depth: Some(100_000),
+            recursion_depth: Some(128),
+            vector_length: Some(1_000_000),
+            int_bits: Some(100_000),
+            sandbox: true,
+        }
+    }
+}
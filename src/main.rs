@@ -1,57 +1,19 @@
 #![allow(unknown_lints)]
 
-#[macro_use] extern crate log;
 extern crate env_logger;
-extern crate shlex;
 extern crate clap;
+extern crate ctrlc;
 #[macro_use]
 extern crate error_chain;
-extern crate num;
+extern crate pnc;
 
 use std::io::prelude::*;
 use std::fs::File;
 
-use clap::{App, AppSettings, Arg};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 
-use errors::*;
-
-mod words;
-mod builtins;
-
-mod errors {
-    use words::Word;
-    error_chain! {
-
-        errors {
-            DivisionByZero {
-                description("division by zero")
-            }
-            MissingOperand {
-                description("operation needs an operand but stack is empty")
-            }
-            WrongTypeOperand(value: ::words::Value, expected: &'static str) {
-                description("operand has a wrong type")
-                display("operand has a wrong type, got operand '{}' (of type {}) but expected type {}", value, value.type_of(), expected)
-            }
-            BlockNoResult {
-                description("block left no result on the stack")
-            }
-            WordParseError(word: Word) {
-                description("could not parse word as number or operation")
-                display("could not parse word '{}' as number or operation", word)
-            }
-            BigIntTooLarge {
-                display("bigint is too large to convert to float")
-            }
-            UnknownWord(word: Word) {
-                display("the word '{}' does not exist", word)
-            }
-        }
-    }
-}
-
-mod calc;
-mod dict;
+use pnc::errors::*;
+use pnc::{cli, Calc, Limits};
 
 quick_main!(run);
 
@@ -64,54 +26,201 @@ fn run() -> Result<()> {
         .setting(AppSettings::TrailingVarArg)
         .setting(AppSettings::AllowLeadingHyphen)
         .setting(AppSettings::UnifiedHelpMessage)
+        .setting(AppSettings::ArgsNegateSubcommands)
+        .setting(AppSettings::DisableHelpSubcommand)
         .arg(Arg::from_usage("[WORD]... 'Words to execute'").use_delimiter(false))
         .arg_from_usage("-q --quiet 'Do not print stack before exiting'")
         .arg_from_usage("-l --list 'List all defined words'")
+        .arg_from_usage("--help-word [NAME] 'Show the documentation of a word'")
+        .arg(Arg::from_usage("--output [FORMAT] 'Format of the stack printed before exiting'")
+             .possible_values(&["text", "json"])
+             .default_value("text"))
+        .arg_from_usage("--check 'Check the stack effects of the words defined in the preludes'")
+        .arg_from_usage("--trace 'Print each executed word with the stack before and after'")
+        .arg_from_usage("--csv 'Read a table from standard input onto the stack before executing words'")
+        .arg(Arg::from_usage("--delimiter [CHAR] 'Delimiter of the table read by --csv, \\t for tab'")
+             .default_value(","))
+        .arg_from_usage("--header 'The first row of the table read by --csv names the columns'")
+        .arg_from_usage("--each [BLOCK] 'Execute BLOCK for the fields of each line of standard input and print the stack'")
+        .arg_from_usage("--begin [BLOCK] 'With --each, execute BLOCK first and keep the stack between lines'")
+        .arg_from_usage("--end [BLOCK] 'With --each, execute BLOCK after the last line and print the stack only then'")
+        .arg_from_usage("--vector 'With --each, push the fields of a line as one vector'")
+        .arg_from_usage("--session [FILE] 'Restore the stack and definitions from FILE, and save them there on exit'")
+        .arg_from_usage("--seed [N] 'Seed the random number generator, for reproducible results'")
+        .arg_from_usage("--sandbox 'Disable input and output words and apply default resource limits'")
+        .arg_from_usage("--max-instructions [N] 'Maximum number of words to execute'")
+        .arg_from_usage("--max-stack [N] 'Maximum number of values on the stack'")
+        .arg_from_usage("--max-depth [N] 'Maximum nesting of defined words'")
+        .arg_from_usage("--max-vector [N] 'Maximum number of elements in a vector'")
+        .arg_from_usage("--max-int-bits [N] 'Maximum number of bits in an integer'")
+        .subcommand(SubCommand::with_name("test")
+                    .about("Run the test-* words in all *_test.pnc files")
+                    .arg_from_usage("[DIR] 'Directory to search for test files (default: .)'"))
         .get_matches();
 
-    let mut calc = calc::Calc::new();
-
-    let builtin_prelude = include_str!("../prelude.pnc");
-    let prelude_words = shlex::Shlex::new(builtin_prelude);
-    calc.run(prelude_words).chain_err(|| "could not execute builtin prelude")?;
-
-    if let Some(mut p) = std::env::home_dir() {
-        p.push(".config/pnc/prelude.pnc");
-        match File::open(&p) {
-            Ok(mut prelude_file) => {
-                let mut prelude = String::new();
-                prelude_file.read_to_string(&mut prelude)
-                    .chain_err(|| format!("could not read user prelude {:?}", p))?;
-
-                let prelude_words = shlex::Shlex::new(&prelude);
-                calc.run(prelude_words).chain_err(|| "failed to execute user prelude")?;
-            }
-            Err(ref e) if p.exists() => {
-                eprintln!("Warning: failed to open user prelude {:?}: {}", p, e);
-            }
-            _ => {}
+    if let Some(args) = args.subcommand_matches("test") {
+        let dir = args.value_of("DIR").unwrap_or(".");
+        let summary = cli::run_dir(std::path::Path::new(dir), &new_calc)?;
+        if summary.failed > 0 {
+            bail!("{} of {} tests failed", summary.failed, summary.passed + summary.failed);
         }
+        return Ok(());
+    }
+
+    let mut calc = new_calc()?;
+    calc.debugger.borrow_mut().trace = args.is_present("trace");
+    calc.set_limits(limits(&args)?);
+    if let Some(seed) = args.value_of("seed") {
+        // Negative seeds are taken modulo 2^64, like the seed word does
+        let value = seed.parse::<u64>().ok().or_else(|| seed.parse::<i64>().ok().map(|i| i as u64));
+        calc.seed(value.ok_or_else(|| format!("invalid value '{}' for --seed", seed))?);
     }
 
     if args.is_present("list") {
         calc.list_available_words();
+    } else if let Some(name) = args.value_of("help-word") {
+        calc.print_help(name)?;
+    } else if args.is_present("check") {
+        let warnings = calc.check();
+        for warning in &warnings {
+            eprintln!("Warning: {}", warning);
+        }
+        if !warnings.is_empty() {
+            bail!("found {} problems in word definitions", warnings.len());
+        }
     } else {
-        if let Some(words) = args.values_of("WORD") {
+        let session = args.value_of("session");
+        if let Some(path) = session {
+            if std::path::Path::new(path).exists() {
+                calc.load_session(path)?;
+            }
+        }
+
+        if let Some(block) = args.value_of("each") {
+            let mut each = cli::Each::new(block).vector(args.is_present("vector"));
+            if let Some(begin) = args.value_of("begin") {
+                each = each.begin(begin);
+            }
+            if let Some(end) = args.value_of("end") {
+                each = each.end(end);
+            }
+            let output = args.value_of("output");
+            let stdin = ::std::io::stdin();
+            each.run(&mut calc, stdin.lock(), |calc| {
+                match output {
+                    Some("json") => calc.print_stack_json(),
+                    _ => calc.print_stack(),
+                }
+            })?;
+        } else if args.is_present("csv") {
+            let delim = args.value_of("delimiter").and_then(cli::delimiter)
+                .ok_or("the delimiter must be a single character")?;
+            let stdin = ::std::io::stdin();
+            let (table, warnings) = cli::read_table(stdin.lock(), delim, args.is_present("header"))?;
+            for warning in &warnings {
+                eprintln!("Warning: {}", warning);
+            }
+            calc.data.push(table);
+            if let Some(words) = args.values_of("WORD") {
+                calc.run(words).chain_err(|| "failed to execute words in arguments")?;
+            }
+        } else if let Some(words) = args.values_of("WORD") {
             calc.run(words).chain_err(|| "failed to execute words in arguments")?;
         } else {
             use std::io::BufRead;
+            // The first Ctrl-C aborts the line being evaluated, a second one
+            // before that happens exits.
+            let interrupt = calc.interrupt_handle();
+            ctrlc::set_handler(move || {
+                if interrupt.request() {
+                    std::process::exit(130);
+                }
+            }).chain_err(|| "failed to set Ctrl-C handler")?;
             let stdin = ::std::io::stdin();
             let stdin = stdin.lock();
             for line in stdin.lines() {
                 let line = line.unwrap();
-                let words = shlex::Shlex::new(&line);
-                calc.run(words).chain_err(|| "failed to execute words from standard in")?;
+                calc.interrupt_handle().clear();
+                let saved = calc.data.clone();
+                match calc.eval(&line) {
+                    Err(ref e) if is_interrupted(e) => {
+                        eprintln!("Interrupted");
+                        calc.data = saved;
+                    }
+                    result => result.chain_err(|| "failed to execute words from standard in")?,
+                }
             }
         }
 
-        if !args.is_present("quiet") {
-            calc.print_stack().chain_err(|| "failed to print stack")?;
+        if let Some(path) = session {
+            calc.save_session(path)?;
+        }
+        // --each has printed the stack already
+        if !args.is_present("quiet") && !args.is_present("each") {
+            match args.value_of("output") {
+                Some("json") => calc.print_stack_json(),
+                _ => calc.print_stack(),
+            }.chain_err(|| "failed to print stack")?;
         }
     }
     Ok(())
 }
+
+fn is_interrupted(err: &Error) -> bool {
+    match *err.root().kind() {
+        ErrorKind::Interrupted => true,
+        _ => false,
+    }
+}
+
+/// Resource limits given on the command line.
+fn limits(args: &ArgMatches) -> Result<Limits> {
+    fn parse<T: std::str::FromStr>(args: &ArgMatches, name: &str, default: Option<T>) -> Result<Option<T>> {
+        match args.value_of(name) {
+            Some(n) => n.parse().map(Some).map_err(|_| format!("invalid value '{}' for --{}", n, name).into()),
+            None => Ok(default),
+        }
+    }
+
+    let defaults = if args.is_present("sandbox") { Limits::sandboxed() } else { Limits::default() };
+    Ok(Limits {
+        instructions: parse(args, "max-instructions", defaults.instructions)?,
+        stack_depth: parse(args, "max-stack", defaults.stack_depth)?,
+        recursion_depth: parse(args, "max-depth", defaults.recursion_depth)?,
+        vector_length: parse(args, "max-vector", defaults.vector_length)?,
+        int_bits: parse(args, "max-int-bits", defaults.int_bits)?,
+        sandbox: defaults.sandbox,
+    })
+}
+
+/// Create a calculator with the builtin and user preludes loaded.
+fn new_calc() -> Result<Calc> {
+    let mut calc = Calc::with_prelude()?;
+
+    if let Some(mut p) = std::env::home_dir() {
+        p.push(".config/pnc/prelude.pnc");
+        match File::open(&p) {
+            Ok(mut prelude_file) => {
+                let mut prelude = String::new();
+                prelude_file.read_to_string(&mut prelude)
+                    .chain_err(|| format!("could not read user prelude {:?}", p))?;
+
+                calc.eval(&prelude).chain_err(|| "failed to execute user prelude")?;
+                calc.new_scope();
+            }
+            Err(ref e) if p.exists() => {
+                eprintln!("Warning: failed to open user prelude {:?}: {}", p, e);
+            }
+            _ => {}
+        }
+
+        p.set_file_name("config");
+        if p.exists() {
+            let mut config = String::new();
+            File::open(&p).and_then(|mut f| f.read_to_string(&mut config))
+                .chain_err(|| format!("could not read config {:?}", p))?;
+            calc.number_format.configure(&config).chain_err(|| format!("invalid config {:?}", p))?;
+        }
+    }
+    Ok(calc)
+}
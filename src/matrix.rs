@@ -0,0 +1,374 @@
+//! Builtin words for matrices, which are vectors of rows of equal length.
+//!
+//! When every entry is an int or a rational the arithmetic is exact,
+//! otherwise it is done with floats. Words taking a matrix on the right
+//! also accept a plain vector, as a column.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use num::{BigInt, One, ToPrimitive, Zero};
+use num::rational::BigRational;
+
+use calc::{float_cast, Calc};
+use errors::*;
+use words::Value;
+
+/// Numbers which matrices are computed with.
+pub trait Scalar: Clone + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+    + Div<Output = Self> + Neg<Output = Self>
+{
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn from_usize(n: usize) -> Self;
+    fn is_zero(&self) -> bool;
+    /// How good a pivot this is. Exact numbers only need to be nonzero, and
+    /// floats are best as large as possible.
+    fn weight(&self) -> f64;
+    /// The relative error of arithmetic, 0 for exact numbers.
+    fn epsilon() -> f64;
+    fn into_value(self) -> Value;
+}
+
+impl Scalar for BigRational {
+    fn zero() -> Self { Zero::zero() }
+    fn one() -> Self { One::one() }
+    fn from_usize(n: usize) -> Self { BigRational::from_integer(BigInt::from(n)) }
+    fn is_zero(&self) -> bool { Zero::is_zero(self) }
+    fn weight(&self) -> f64 { if Zero::is_zero(self) { 0.0 } else { 1.0 } }
+    fn epsilon() -> f64 { 0.0 }
+    fn into_value(self) -> Value { Value::from_rational(self) }
+}
+
+impl Scalar for f64 {
+    fn zero() -> Self { 0.0 }
+    fn one() -> Self { 1.0 }
+    fn from_usize(n: usize) -> Self { n as f64 }
+    fn is_zero(&self) -> bool { self.abs() < 1e-12 }
+    fn weight(&self) -> f64 { self.abs() }
+    fn epsilon() -> f64 { ::std::f64::EPSILON }
+    fn into_value(self) -> Value { Value::Float(self) }
+}
+
+/// Entries of several matrices, all exact or all floats.
+pub enum Entries {
+    Exact(Vec<Vec<Vec<BigRational>>>),
+    Float(Vec<Vec<Vec<f64>>>),
+}
+
+pub fn entries(matrices: Vec<Vec<Vec<Value>>>) -> Result<Entries> {
+    let exact = matrices.iter().all(|m| m.iter().all(|row| row.iter().all(|x| x.as_rational().is_some())));
+    if exact {
+        return Ok(Entries::Exact(matrices.iter().map(|m| {
+            m.iter().map(|row| row.iter().map(|x| x.as_rational().unwrap()).collect()).collect()
+        }).collect()));
+    }
+    let mut floats = Vec::new();
+    for m in matrices {
+        let mut rows = Vec::new();
+        for row in m {
+            rows.push(row.into_iter().map(float_cast).collect::<Result<Vec<_>>>()?);
+        }
+        floats.push(rows);
+    }
+    Ok(Entries::Float(floats))
+}
+
+fn is_vector(value: &Value) -> bool {
+    match *value {
+        Value::Vector(_) => true,
+        _ => false,
+    }
+}
+
+/// The rows of `value`, which must be a non-empty vector of vectors of the
+/// same length.
+fn rows(value: Value) -> Result<Vec<Vec<Value>>> {
+    let rows: Vec<Vec<Value>> = match value {
+        Value::Vector(ref vec) if !vec.is_empty() && vec.iter().all(is_vector) => {
+            vec.iter().cloned().filter_map(|row| row.into_vector().ok()).collect()
+        }
+        value => return Err(ErrorKind::WrongTypeOperand(value, "matrix").into()),
+    };
+    let width = rows[0].len();
+    for row in &rows {
+        if row.len() != width {
+            return Err(ErrorKind::LengthMismatch(width, row.len()).into());
+        }
+    }
+    Ok(rows)
+}
+
+fn square(value: Value) -> Result<Vec<Vec<Value>>> {
+    let m = rows(value.clone())?;
+    if m.len() != m[0].len() {
+        return Err(ErrorKind::WrongTypeOperand(value, "square matrix").into());
+    }
+    Ok(m)
+}
+
+/// The rows of `value` if it is a matrix, or of a single column if it is a
+/// vector of numbers. The flag tells which.
+fn rows_or_column(value: Value) -> Result<(Vec<Vec<Value>>, bool)> {
+    match value {
+        Value::Vector(ref vec) if !vec.is_empty() && !vec.iter().any(is_vector) => {
+            Ok((vec.iter().map(|x| vec![x.clone()]).collect(), true))
+        }
+        value => Ok((rows(value)?, false)),
+    }
+}
+
+fn to_value<T: Scalar>(m: Vec<Vec<T>>, column: bool) -> Value {
+    if column {
+        Value::Vector(m.into_iter().map(|mut row| row.remove(0).into_value()).collect())
+    } else {
+        Value::Vector(m.into_iter()
+            .map(|row| Value::Vector(row.into_iter().map(Scalar::into_value).collect()))
+            .collect())
+    }
+}
+
+pub fn dot<T: Scalar>(u: &[T], v: &[T]) -> T {
+    u.iter().zip(v).fold(T::zero(), |sum, (x, y)| sum + x.clone() * y.clone())
+}
+
+pub fn product<T: Scalar>(a: &[Vec<T>], b: &[Vec<T>]) -> Result<Vec<Vec<T>>> {
+    if a[0].len() != b.len() {
+        return Err(ErrorKind::LengthMismatch(a[0].len(), b.len()).into());
+    }
+    let columns: Vec<Vec<T>> = (0..b[0].len()).map(|j| b.iter().map(|row| row[j].clone()).collect()).collect();
+    Ok(a.iter().map(|row| columns.iter().map(|column| dot(row, column)).collect()).collect())
+}
+
+/// Reduce `a` to the identity by Gauss-Jordan elimination, applying the same
+/// row operations to `b`. Returns the determinant of `a`, and `b` turned into
+/// the solution of `a x = b` unless `a` is singular.
+pub fn eliminate<T: Scalar>(mut a: Vec<Vec<T>>, mut b: Vec<Vec<T>>) -> (T, Option<Vec<Vec<T>>>) {
+    let n = a.len();
+    // Pivots this small are rounding errors of a singular matrix
+    let largest = a.iter().flat_map(|row| row.iter().map(Scalar::weight)).fold(0.0, f64::max);
+    let tol = T::epsilon() * n as f64 * largest;
+    let mut det = T::one();
+    for col in 0..n {
+        let mut pivot = col;
+        for row in col + 1..n {
+            if a[row][col].weight() > a[pivot][col].weight() {
+                pivot = row;
+            }
+        }
+        if a[pivot][col].weight() <= tol {
+            return (T::zero(), None);
+        }
+        if pivot != col {
+            a.swap(pivot, col);
+            b.swap(pivot, col);
+            det = -det;
+        }
+        let p = a[col][col].clone();
+        det = det * p.clone();
+        for x in a[col].iter_mut().chain(b[col].iter_mut()) {
+            *x = x.clone() / p.clone();
+        }
+        for row in 0..n {
+            let factor = a[row][col].clone();
+            if row == col || factor.weight() == 0.0 {
+                continue;
+            }
+            for k in 0..n {
+                let x = a[col][k].clone();
+                a[row][k] = a[row][k].clone() - factor.clone() * x;
+            }
+            for k in 0..b[row].len() {
+                let x = b[col][k].clone();
+                b[row][k] = b[row][k].clone() - factor.clone() * x;
+            }
+        }
+    }
+    (det, Some(b))
+}
+
+fn identity<T: Scalar>(n: usize) -> Vec<Vec<T>> {
+    (0..n).map(|i| (0..n).map(|j| if i == j { T::one() } else { T::zero() }).collect()).collect()
+}
+
+fn determinant<T: Scalar>(mut ms: Vec<Vec<Vec<T>>>) -> Value {
+    let a = ms.remove(0);
+    let n = a.len();
+    eliminate(a, vec![vec![]; n]).0.into_value()
+}
+
+fn inverse<T: Scalar>(mut ms: Vec<Vec<Vec<T>>>) -> Result<Value> {
+    let a = ms.remove(0);
+    let n = a.len();
+    let inv = eliminate(a, identity(n)).1.ok_or(ErrorKind::SingularMatrix)?;
+    Ok(to_value(inv, false))
+}
+
+fn solution<T: Scalar>(mut ms: Vec<Vec<Vec<T>>>, column: bool) -> Result<Value> {
+    let b = ms.pop().unwrap();
+    let a = ms.pop().unwrap();
+    if a.len() != b.len() {
+        return Err(ErrorKind::LengthMismatch(a.len(), b.len()).into());
+    }
+    let x = eliminate(a, b).1.ok_or(ErrorKind::SingularMatrix)?;
+    Ok(to_value(x, column))
+}
+
+fn multiply<T: Scalar>(mut ms: Vec<Vec<Vec<T>>>, column: bool) -> Result<Value> {
+    let b = ms.pop().unwrap();
+    let a = ms.pop().unwrap();
+    Ok(to_value(product(&a, &b)?, column))
+}
+
+fn dot_product<T: Scalar>(mut ms: Vec<Vec<Vec<T>>>) -> Value {
+    let v = ms.pop().unwrap().remove(0);
+    let u = ms.pop().unwrap().remove(0);
+    dot(&u, &v).into_value()
+}
+
+fn cross_product<T: Scalar>(mut ms: Vec<Vec<Vec<T>>>) -> Value {
+    let v = ms.pop().unwrap().remove(0);
+    let u = ms.pop().unwrap().remove(0);
+    let component = |i: usize, j: usize| u[i].clone() * v[j].clone() - u[j].clone() * v[i].clone();
+    Value::Vector(vec![component(1, 2).into_value(), component(2, 0).into_value(), component(0, 1).into_value()])
+}
+
+impl Calc {
+    /// Two vectors of numbers of the same length, as single-row matrices.
+    fn get_vector_pair(&mut self) -> Result<Vec<Vec<Vec<Value>>>> {
+        let v = self.get_vector()?;
+        let u = self.get_vector()?;
+        if u.len() != v.len() {
+            return Err(ErrorKind::LengthMismatch(u.len(), v.len()).into());
+        }
+        Ok(vec![vec![u], vec![v]])
+    }
+
+    pub fn builtin_transpose(&mut self) -> Result<()> {
+        let m = rows(self.get_collected()?)?;
+        let transposed = (0..m[0].len()).map(|j| Value::Vector(m.iter().map(|row| row[j].clone()).collect()));
+        self.data.push(Value::Vector(transposed.collect()));
+        Ok(())
+    }
+
+    pub fn builtin_matmul(&mut self) -> Result<()> {
+        let (b, column) = rows_or_column(self.get_collected()?)?;
+        let a = rows(self.get_collected()?)?;
+        let value = match entries(vec![a, b])? {
+            Entries::Exact(ms) => multiply(ms, column)?,
+            Entries::Float(ms) => multiply(ms, column)?,
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_det(&mut self) -> Result<()> {
+        let a = square(self.get_collected()?)?;
+        let value = match entries(vec![a])? {
+            Entries::Exact(ms) => determinant(ms),
+            Entries::Float(ms) => determinant(ms),
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_inv(&mut self) -> Result<()> {
+        let a = square(self.get_collected()?)?;
+        let value = match entries(vec![a])? {
+            Entries::Exact(ms) => inverse(ms)?,
+            Entries::Float(ms) => inverse(ms)?,
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_solve(&mut self) -> Result<()> {
+        let (b, column) = rows_or_column(self.get_collected()?)?;
+        let a = square(self.get_collected()?)?;
+        let value = match entries(vec![a, b])? {
+            Entries::Exact(ms) => solution(ms, column)?,
+            Entries::Float(ms) => solution(ms, column)?,
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_identity(&mut self) -> Result<()> {
+        let n = self.get_int()?;
+        let n = n.to_usize().ok_or_else(|| Error::from(ErrorKind::WrongTypeOperand(Value::Int(n), "positive int")))?;
+        self.check_vector_len(n.saturating_mul(n))?;
+        let one = || Value::Int(BigInt::one());
+        let zero = || Value::Int(BigInt::zero());
+        let m = (0..n).map(|i| Value::Vector((0..n).map(|j| if i == j { one() } else { zero() }).collect()));
+        self.data.push(Value::Vector(m.collect()));
+        Ok(())
+    }
+
+    pub fn builtin_dot(&mut self) -> Result<()> {
+        let value = match entries(self.get_vector_pair()?)? {
+            Entries::Exact(ms) => dot_product(ms),
+            Entries::Float(ms) => dot_product(ms),
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_cross(&mut self) -> Result<()> {
+        let vs = self.get_vector_pair()?;
+        if vs[0][0].len() != 3 {
+            return Err(ErrorKind::WrongTypeOperand(Value::Vector(vs[0][0].clone()), "vector of length 3").into());
+        }
+        let value = match entries(vs)? {
+            Entries::Exact(ms) => cross_product(ms),
+            Entries::Float(ms) => cross_product(ms),
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_norm(&mut self) -> Result<()> {
+        let mut sum = 0.0;
+        for x in self.get_vector()? {
+            let x = float_cast(x)?;
+            sum += x * x;
+        }
+        self.data.push(Value::Float(sum.sqrt()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use calc::Calc;
+    use testing::{assert_cases, assert_errors};
+
+    #[test]
+    fn test_matrix() {
+        assert_cases(&[
+            ("[ [ 1 2 3 ] [ 4 5 6 ] ] transpose", "[ [ 1 4 ] [ 2 5 ] [ 3 6 ] ]"),
+            ("[ [ 1 2 ] [ 3 4 ] ] [ [ 5 ] [ 6 ] ] matmul", "[ [ 17 ] [ 39 ] ]"),
+            ("[ [ 1 2 ] [ 3 4 ] ] [ 5 6 ] matmul", "[ 17 39 ]"),
+            ("[ [ 1 2 ] [ 3 4 ] ] det", "-2"),
+            ("[ [ 0 1 ] [ 1 0 ] ] det [ [ 1 2 ] [ 2 4 ] ] det", "-1 0"),
+            ("[ [ 2 1 ] [ 1 1 ] ] inv", "[ [ 1 -1 ] [ -1 2 ] ]"),
+            ("[ [ 1 2 ] [ 3 4 ] ] inv", "[ [ -2 1 ] [ 3/2 -1/2 ] ]"),
+            ("[ [ 2 1 ] [ 1 3 ] ] [ 3 5 ] solve", "[ 4/5 7/5 ]"),
+            ("[ [ 2.0 0 ] [ 0 4 ] ] [ 1 1 ] solve", "[ 0.5 0.25 ]"),
+            ("[ [ 1e-7 0 ] [ 0 1e-7 ] ] [ 1e-7 2e-7 ] solve", "[ 1.0 2.0 ]"),
+            ("[ [ 1e-7 0 ] [ 0 1e-7 ] ] inv 0 nth", "[ 10000000.0 0.0 ]"),
+            ("2 identity", "[ [ 1 0 ] [ 0 1 ] ]"),
+            ("[ 1 2 3 ] [ 4 5 6 ] dot", "32"),
+            ("[ 1 0 0 ] [ 0 1 0 ] cross", "[ 0 0 1 ]"),
+            ("[ 3 -4 ] norm", "5.0"),
+            ("2 upto { dup 2 pack } map transpose 3 upto 3 upto dot", "[ [ 1 2 ] [ 1 2 ] ] 14"),
+        ]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut calc = Calc::with_prelude().unwrap();
+        let singular = calc.eval("[ [ 1 2 ] [ 2 4 ] ] inv").unwrap_err();
+        assert_eq!(singular.root().kind().name(), "singular-matrix");
+        assert_errors(&["[ [ 1 2 ] [ 3 ] ] det", "[ [ 1 2 ] ] det", "[ 1 2 ] det",
+                        "[ [ 1 2 ] ] [ 1 2 3 ] matmul", "[ 1 2 ] [ 3 4 ] cross"]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Numerical methods on functions given as blocks, which take a float and
+//! leave a number.
+
+use std::cell::Cell;
+
+use calc::{float_cast, Calc};
+use errors::*;
+use words::{Value, Word};
+
+/// Tolerance of `integrate`, `root` and `minimize`. For `integrate` it is
+/// relative to the magnitude of the first estimate, if that is above 1.
+const TOLERANCE: f64 = 1e-10;
+const MAX_ITERATIONS: usize = 200;
+const MAX_EVALUATIONS: usize = 100_000;
+
+/// Simpson's rule on `[a, b]`, returning the midpoint, the value there and
+/// the estimate.
+fn simpson<F>(f: &F, a: f64, fa: f64, b: f64, fb: f64) -> Result<(f64, f64, f64)>
+    where F: Fn(f64) -> Result<f64>
+{
+    let m = (a + b) / 2.0;
+    let fm = f(m)?;
+    Ok((m, fm, (b - a) / 6.0 * (fa + 4.0 * fm + fb)))
+}
+
+/// Split `[a, b]` in two until Simpson's rule agrees with itself within `tol`.
+fn adaptive_simpson<F>(f: &F, (a, fa): (f64, f64), (m, fm): (f64, f64), (b, fb): (f64, f64),
+                       whole: f64, tol: f64, depth: u32) -> Result<f64>
+    where F: Fn(f64) -> Result<f64>
+{
+    let (lm, flm, left) = simpson(f, a, fa, m, fm)?;
+    let (rm, frm, right) = simpson(f, m, fm, b, fb)?;
+    let delta = left + right - whole;
+    if depth == 0 || delta.abs() <= 15.0 * tol {
+        return Ok(left + right + delta / 15.0);
+    }
+    Ok(adaptive_simpson(f, (a, fa), (lm, flm), (m, fm), left, tol / 2.0, depth - 1)?
+       + adaptive_simpson(f, (m, fm), (rm, frm), (b, fb), right, tol / 2.0, depth - 1)?)
+}
+
+pub fn integrate<F>(f: &F, a: f64, b: f64) -> Result<f64>
+    where F: Fn(f64) -> Result<f64>
+{
+    let evaluations = Cell::new(0);
+    let f = |x| {
+        evaluations.set(evaluations.get() + 1);
+        if evaluations.get() > MAX_EVALUATIONS {
+            bail!("integration did not converge after {} evaluations", MAX_EVALUATIONS);
+        }
+        let y = f(x)?;
+        if y.is_nan() {
+            bail!("cannot integrate: the function is not a number at {}", x);
+        }
+        Ok(y)
+    };
+    let (fa, fb) = (f(a)?, f(b)?);
+    let (m, fm, whole) = simpson(&f, a, fa, b, fb)?;
+    let tol = TOLERANCE * whole.abs().max(1.0);
+    adaptive_simpson(&f, (a, fa), (m, fm), (b, fb), whole, tol, 40)
+}
+
+/// The derivative at `x` by the five-point central difference.
+pub fn deriv<F>(f: &F, x: f64) -> Result<f64>
+    where F: Fn(f64) -> Result<f64>
+{
+    let h = 1e-3 * x.abs().max(1.0);
+    Ok((f(x - 2.0 * h)? - 8.0 * f(x - h)? + 8.0 * f(x + h)? - f(x + 2.0 * h)?) / (12.0 * h))
+}
+
+/// A zero of `f` in `[a, b]` by Brent's method. `f(a)` and `f(b)` must not
+/// have the same sign.
+pub fn root<F>(f: &F, a: f64, b: f64) -> Result<f64>
+    where F: Fn(f64) -> Result<f64>
+{
+    let (mut a, mut b, mut c) = (a, b, b);
+    let (mut fa, mut fb) = (f(a)?, f(b)?);
+    if fa * fb > 0.0 {
+        bail!("no root between {} and {}: the function has the same sign at both ends", a, b);
+    }
+    let mut fc = fb;
+    let (mut d, mut e) = (b - a, b - a);
+    for _ in 0..MAX_ITERATIONS {
+        if fb * fc > 0.0 {
+            c = a;
+            fc = fa;
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            a = b;
+            b = c;
+            c = a;
+            fa = fb;
+            fb = fc;
+            fc = fa;
+        }
+        let tol = 2.0 * ::std::f64::EPSILON * b.abs() + TOLERANCE / 2.0;
+        let xm = (c - b) / 2.0;
+        if xm.abs() <= tol || fb == 0.0 {
+            return Ok(b);
+        }
+        if e.abs() >= tol && fa.abs() > fb.abs() {
+            // Inverse quadratic interpolation, or the secant method when
+            // only two points are known.
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * xm * s, 1.0 - s)
+            } else {
+                let q = fa / fc;
+                let r = fb / fc;
+                (s * (2.0 * xm * q * (q - r) - (b - a) * (r - 1.0)), (q - 1.0) * (r - 1.0) * (s - 1.0))
+            };
+            if p > 0.0 {
+                q = -q;
+            }
+            p = p.abs();
+            if 2.0 * p < (3.0 * xm * q - (tol * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = xm;
+                e = d;
+            }
+        } else {
+            d = xm;
+            e = d;
+        }
+        a = b;
+        fa = fb;
+        b += if d.abs() > tol { d } else if xm > 0.0 { tol } else { -tol };
+        fb = f(b)?;
+    }
+    bail!("root finding did not converge after {} iterations", MAX_ITERATIONS)
+}
+
+/// A local minimum of `f` in `[a, b]` by golden-section search.
+pub fn minimize<F>(f: &F, a: f64, b: f64) -> Result<f64>
+    where F: Fn(f64) -> Result<f64>
+{
+    let ratio = (5f64.sqrt() - 1.0) / 2.0;
+    let (mut a, mut b) = (a.min(b), a.max(b));
+    let mut x1 = b - ratio * (b - a);
+    let mut x2 = a + ratio * (b - a);
+    let (mut f1, mut f2) = (f(x1)?, f(x2)?);
+    for _ in 0..MAX_ITERATIONS {
+        if b - a <= TOLERANCE * (1.0 + x1.abs()) {
+            break;
+        }
+        if f1 < f2 {
+            b = x2;
+            x2 = x1;
+            f2 = f1;
+            x1 = b - ratio * (b - a);
+            f1 = f(x1)?;
+        } else {
+            a = x1;
+            x1 = x2;
+            f1 = f2;
+            x2 = a + ratio * (b - a);
+            f2 = f(x2)?;
+        }
+    }
+    Ok((a + b) / 2.0)
+}
+
+impl Calc {
+    /// The block on top of the stack as a function, and the interval below it.
+    fn get_function_on_interval(&mut self) -> Result<(Vec<Word>, f64, f64)> {
+        let block = self.get_block()?;
+        let b = self.get_float_cast()?;
+        let a = self.get_float_cast()?;
+        Ok((block, a, b))
+    }
+
+    fn apply_function(&self, block: &[Word], x: f64) -> Result<f64> {
+        float_cast(self.apply_block(block, vec![Value::Float(x)])?)
+    }
+
+    pub fn builtin_integrate(&mut self) -> Result<()> {
+        let (block, a, b) = self.get_function_on_interval()?;
+        let area = integrate(&|x| self.apply_function(&block, x), a, b)?;
+        self.data.push(Value::Float(area));
+        Ok(())
+    }
+
+    pub fn builtin_deriv(&mut self) -> Result<()> {
+        let block = self.get_block()?;
+        let x = self.get_float_cast()?;
+        let slope = deriv(&|x| self.apply_function(&block, x), x)?;
+        self.data.push(Value::Float(slope));
+        Ok(())
+    }
+
+    pub fn builtin_root(&mut self) -> Result<()> {
+        let (block, a, b) = self.get_function_on_interval()?;
+        let x = root(&|x| self.apply_function(&block, x), a, b)?;
+        self.data.push(Value::Float(x));
+        Ok(())
+    }
+
+    pub fn builtin_minimize(&mut self) -> Result<()> {
+        let (block, a, b) = self.get_function_on_interval()?;
+        let x = minimize(&|x| self.apply_function(&block, x), a, b)?;
+        self.data.push(Value::Float(x));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testing::{assert_errors, eval_float};
+
+    #[test]
+    fn test_numeric() {
+        let cases = [
+            ("0 1 { dup . } integrate", 1.0 / 3.0),
+            ("0 pi { sin } integrate", 2.0),
+            ("0 1000 { dup dup . dup . . } integrate", 1e18 / 6.0),
+            ("2 { dup dup . . } deriv", 12.0),
+            ("0 { exp } deriv", 1.0),
+            ("1 2 { dup . 2 - } root", 2f64.sqrt()),
+            ("0 3 { cos } root", ::std::f64::consts::PI / 2.0),
+            ("0 5 { 2 - dup . } minimize", 2.0),
+        ];
+        for &(source, expected) in &cases {
+            let x = eval_float(source);
+            assert!((x - expected).abs() < 1e-7 * expected.abs().max(1.0), "{}: {} != {}", source, x, expected);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_errors(&["1 2 { dup . } root", "0 1 { pop ,x } integrate", "0 1 { pop } integrate",
+                        "0 1e6 { sin } integrate", "-1 1 { sqrt } integrate"]);
+    }
+}
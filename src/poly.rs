@@ -0,0 +1,269 @@
+//! Builtin words for polynomials, which are vectors of coefficients with
+//! the highest power first, so `[ 1 0 -2 ]` is x² - 2.
+//!
+//! Like matrices, polynomials with int and rational coefficients are
+//! computed exactly.
+
+use std::cmp::Ordering;
+
+use num::ToPrimitive;
+use num::complex::Complex64;
+
+use calc::{float_cast, Calc};
+use errors::*;
+use matrix::{self, Entries, Scalar};
+use words::Value;
+
+/// Convergence tolerance of `polyroots`.
+const ROOT_TOLERANCE: f64 = 1e-12;
+const MAX_ITERATIONS: usize = 500;
+
+fn to_value<T: Scalar>(p: Vec<T>) -> Value {
+    Value::Vector(p.into_iter().map(Scalar::into_value).collect())
+}
+
+/// `p` without leading zero coefficients, but at least one coefficient.
+fn trim<T: Scalar>(mut p: Vec<T>) -> Vec<T> {
+    let zeros = p.iter().take_while(|c| c.is_zero()).count();
+    p.drain(..zeros.min(p.len().saturating_sub(1)));
+    p
+}
+
+fn eval<T: Scalar>(p: &[T], x: T) -> T {
+    p.iter().fold(T::zero(), |y, c| y * x.clone() + c.clone())
+}
+
+fn add<T: Scalar>(p: &[T], q: &[T]) -> Vec<T> {
+    let (long, short) = if p.len() >= q.len() { (p, q) } else { (q, p) };
+    let offset = long.len() - short.len();
+    let sum = long.iter().enumerate().map(|(i, c)| {
+        if i < offset { c.clone() } else { c.clone() + short[i - offset].clone() }
+    });
+    trim(sum.collect())
+}
+
+fn mul<T: Scalar>(p: &[T], q: &[T]) -> Vec<T> {
+    let mut product = vec![T::zero(); p.len() + q.len() - 1];
+    for (i, a) in p.iter().enumerate() {
+        for (j, b) in q.iter().enumerate() {
+            product[i + j] = product[i + j].clone() + a.clone() * b.clone();
+        }
+    }
+    trim(product)
+}
+
+fn derivative<T: Scalar>(p: &[T]) -> Vec<T> {
+    let degree = p.len() - 1;
+    if degree == 0 {
+        return vec![T::zero()];
+    }
+    trim(p[..degree].iter().enumerate().map(|(i, c)| c.clone() * T::from_usize(degree - i)).collect())
+}
+
+fn integral<T: Scalar>(p: &[T]) -> Vec<T> {
+    let degree = p.len() - 1;
+    let mut integral: Vec<T> = p.iter().enumerate().map(|(i, c)| c.clone() / T::from_usize(degree - i + 1)).collect();
+    integral.push(T::zero());
+    trim(integral)
+}
+
+/// Least-squares fit of a polynomial of degree `n` through the points, by
+/// solving the normal equations.
+fn fit<T: Scalar>(xs: &[T], ys: &[T], n: usize) -> Result<Vec<T>> {
+    let vandermonde: Vec<Vec<T>> = xs.iter().map(|x| {
+        let mut powers = vec![T::one()];
+        for _ in 0..n {
+            let power = powers[0].clone() * x.clone();
+            powers.insert(0, power);
+        }
+        powers
+    }).collect();
+    let transposed: Vec<Vec<T>> = (0..n + 1).map(|j| vandermonde.iter().map(|row| row[j].clone()).collect()).collect();
+    let normal = matrix::product(&transposed, &vandermonde)?;
+    let rhs = matrix::product(&transposed, &ys.iter().map(|y| vec![y.clone()]).collect::<Vec<_>>())?;
+    let solution = matrix::eliminate(normal, rhs).1.ok_or(ErrorKind::SingularMatrix)?;
+    Ok(solution.into_iter().map(|mut row| row.remove(0)).collect())
+}
+
+/// All complex roots of `p` by the Durand-Kerner method.
+fn roots(p: &[f64]) -> Vec<Complex64> {
+    let p = trim(p.to_vec());
+    let degree = p.len() - 1;
+    if degree == 0 {
+        return vec![];
+    }
+    let monic: Vec<Complex64> = p.iter().map(|c| Complex64::new(c / p[0], 0.0)).collect();
+    let eval = |z: Complex64| monic.iter().fold(Complex64::new(0.0, 0.0), |y, c| y * z + c);
+    let seed = Complex64::new(0.4, 0.9);
+    let mut roots = vec![Complex64::new(1.0, 0.0)];
+    while roots.len() < degree {
+        let next = roots[roots.len() - 1] * seed;
+        roots.push(next);
+    }
+    for _ in 0..MAX_ITERATIONS {
+        let mut change: f64 = 0.0;
+        for i in 0..degree {
+            let denominator = roots.iter().enumerate()
+                .filter(|&(j, _)| j != i)
+                .fold(Complex64::new(1.0, 0.0), |d, (_, r)| d * (roots[i] - r));
+            let step = eval(roots[i]) / denominator;
+            roots[i] = roots[i] - step;
+            change = change.max(step.norm());
+        }
+        if change < ROOT_TOLERANCE {
+            break;
+        }
+    }
+    for root in &mut roots {
+        if root.im.abs() < 1e-9 * root.re.abs().max(1.0) {
+            root.im = 0.0;
+        }
+    }
+    roots.sort_by(|a, b| {
+        a.re.partial_cmp(&b.re).unwrap_or(Ordering::Equal)
+            .then(a.im.partial_cmp(&b.im).unwrap_or(Ordering::Equal))
+    });
+    roots
+}
+
+impl Calc {
+    /// Polynomials from the top `n` operands, in stack order.
+    fn get_polynomials(&mut self, n: usize) -> Result<Vec<Vec<Vec<Value>>>> {
+        let mut polys = Vec::with_capacity(n);
+        for _ in 0..n {
+            let p = self.get_vector()?;
+            if p.is_empty() {
+                return Err(ErrorKind::WrongTypeOperand(Value::Vector(p), "polynomial").into());
+            }
+            polys.insert(0, vec![p]);
+        }
+        Ok(polys)
+    }
+
+    pub fn builtin_polyval(&mut self) -> Result<()> {
+        let x = self.get_operand()?;
+        let mut polys = self.get_polynomials(1)?;
+        polys.push(vec![vec![x]]);
+        let value = match matrix::entries(polys)? {
+            Entries::Exact(ps) => eval(&ps[0][0], ps[1][0][0].clone()).into_value(),
+            Entries::Float(ps) => eval(&ps[0][0], ps[1][0][0]).into_value(),
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_polyadd(&mut self) -> Result<()> {
+        let value = match matrix::entries(self.get_polynomials(2)?)? {
+            Entries::Exact(ps) => to_value(add(&ps[0][0], &ps[1][0])),
+            Entries::Float(ps) => to_value(add(&ps[0][0], &ps[1][0])),
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_polymul(&mut self) -> Result<()> {
+        let value = match matrix::entries(self.get_polynomials(2)?)? {
+            Entries::Exact(ps) => to_value(mul(&ps[0][0], &ps[1][0])),
+            Entries::Float(ps) => to_value(mul(&ps[0][0], &ps[1][0])),
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_polyder(&mut self) -> Result<()> {
+        let value = match matrix::entries(self.get_polynomials(1)?)? {
+            Entries::Exact(ps) => to_value(derivative(&ps[0][0])),
+            Entries::Float(ps) => to_value(derivative(&ps[0][0])),
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_polyint(&mut self) -> Result<()> {
+        let value = match matrix::entries(self.get_polynomials(1)?)? {
+            Entries::Exact(ps) => to_value(integral(&ps[0][0])),
+            Entries::Float(ps) => to_value(integral(&ps[0][0])),
+        };
+        self.data.push(value);
+        Ok(())
+    }
+
+    pub fn builtin_polyroots(&mut self) -> Result<()> {
+        let p = match matrix::entries(self.get_polynomials(1)?)? {
+            Entries::Exact(ps) => {
+                ps[0][0].iter().map(|c| float_cast(Value::from_rational(c.clone()))).collect::<Result<Vec<_>>>()?
+            }
+            Entries::Float(mut ps) => ps.remove(0).remove(0),
+        };
+        let roots = roots(&p).into_iter()
+            .map(|z| Value::Vector(vec![Value::Float(z.re), Value::Float(z.im)]));
+        self.data.push(Value::Vector(roots.collect()));
+        Ok(())
+    }
+
+    pub fn builtin_polyfit(&mut self) -> Result<()> {
+        let n = self.get_int()?;
+        let n = n.to_usize().ok_or_else(|| Error::from(ErrorKind::WrongTypeOperand(Value::Int(n), "positive int")))?;
+        let ys = self.get_vector()?;
+        let xs = self.get_vector()?;
+        if xs.len() != ys.len() {
+            return Err(ErrorKind::LengthMismatch(xs.len(), ys.len()).into());
+        }
+        if n >= xs.len() {
+            bail!("cannot fit a polynomial of degree {} through {} points", n, xs.len());
+        }
+        let value = match matrix::entries(vec![vec![xs, ys]])? {
+            Entries::Exact(ps) => to_value(fit(&ps[0][0], &ps[0][1], n)?),
+            Entries::Float(ps) => to_value(fit(&ps[0][0], &ps[0][1], n)?),
+        };
+        self.data.push(value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testing::{assert_cases, assert_errors, eval};
+    use words::Value;
+
+    #[test]
+    fn test_poly() {
+        assert_cases(&[
+            ("[ 1 0 -2 ] 3 polyval", "7"),
+            ("3 upto 2 polyval", "11"),
+            ("[ 1/2 1 ] 1/3 polyval", "7/6"),
+            ("[ 1.5 0 ] 2 polyval", "3.0"),
+            ("[ 1 2 3 ] [ -1 0 1 ] polyadd", "[ 2 4 ]"),
+            ("[ 1 1 ] [ 1 -1 ] polymul", "[ 1 0 -1 ]"),
+            ("[ 3 2 1 ] polyder [ 5 ] polyder", "[ 6 2 ] [ 0 ]"),
+            ("[ 0 3 2 1 ] polyder [ 0.0 1.5 1 ] polyder", "[ 6 2 ] [ 1.5 ]"),
+            ("[ 3 2 1 ] polyint", "[ 1 1 1 0 ]"),
+            ("[ 1 1 ] polyint", "[ 1/2 1 0 ]"),
+            ("[ 0 1 2 ] [ 1 3 5 ] 1 polyfit", "[ 2 1 ]"),
+            ("[ 0 1 2 3 ] [ 0 1 1 3 ] 1 polyfit", "[ 9/10 -1/10 ]"),
+        ]);
+        assert_errors(&["[ 1 2 ] [ 1 2 ] 2 polyfit", "[ 1 2 ] [ 1 2 ] 30000 polyfit"]);
+    }
+
+    #[test]
+    fn test_polyroots() {
+        let cases: [(&str, &[(f64, f64)]); 4] = [
+            ("[ 1 -3 2 ] polyroots", &[(1.0, 0.0), (2.0, 0.0)]),
+            ("[ 1 0 1 ] polyroots", &[(0.0, -1.0), (0.0, 1.0)]),
+            ("[ 0 2 -1 ] polyroots", &[(0.5, 0.0)]),
+            ("[ 2.5 ] polyroots", &[]),
+        ];
+        for &(source, expected) in &cases {
+            let roots = match eval(source).pop() {
+                Some(Value::Vector(roots)) => roots,
+                v => panic!("{}: not a vector: {:?}", source, v),
+            };
+            assert_eq!(roots.len(), expected.len(), "{}", source);
+            for (root, &(re, im)) in roots.into_iter().zip(expected) {
+                let z = root.into_vector().unwrap();
+                assert!((z[0].as_float().unwrap() - re).abs() < 1e-9, "{}", source);
+                assert_eq!(z[1].as_float().unwrap(), im, "{}", source);
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Random numbers, and builtin words using them.
+//!
+//! The generator is xoshiro256** seeded with splitmix64. It is implemented
+//! here rather than taken from a crate so that a seed gives the same numbers
+//! in every version of pnc.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use num::{BigInt, ToPrimitive};
+
+use calc::Calc;
+use errors::*;
+use words::Value;
+
+/// Mean above which Poisson variables are drawn from a normal distribution.
+const NORMAL_POISSON_MEAN: f64 = 1000.0;
+/// Largest mean of a Poisson variable, which keeps the result in a `u64`.
+const MAX_POISSON_MEAN: f64 = 1e18;
+
+pub struct Rng {
+    state: [u64; 4],
+}
+
+impl Rng {
+    pub fn with_seed(seed: u64) -> Rng {
+        let mut x = seed;
+        let mut splitmix = || {
+            x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        Rng { state: [splitmix(), splitmix(), splitmix(), splitmix()] }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+
+    /// A float uniformly distributed in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An integer uniformly distributed in `[0, n)`, for `n > 0`.
+    pub fn below(&mut self, n: u64) -> u64 {
+        // Reject the top values which would make some results more likely
+        let zone = u64::max_value() - u64::max_value() % n;
+        loop {
+            let x = self.next_u64();
+            if x < zone {
+                return x % n;
+            }
+        }
+    }
+
+    pub fn normal(&mut self) -> f64 {
+        // Box-Muller, with 1 - u in (0, 1] to keep the logarithm finite
+        let u = 1.0 - self.next_f64();
+        let v = self.next_f64();
+        (-2.0 * u.ln()).sqrt() * (2.0 * ::std::f64::consts::PI * v).cos()
+    }
+
+    /// Knuth's method, run on chunks of the mean so `e^-mean` does not
+    /// underflow. The sum of Poisson variables is a Poisson variable. Above
+    /// `NORMAL_POISSON_MEAN` that would take too many draws, and the normal
+    /// approximation is used instead.
+    pub fn poisson(&mut self, mean: f64) -> u64 {
+        if mean > NORMAL_POISSON_MEAN {
+            return (mean + mean.sqrt() * self.normal()).round().max(0.0) as u64;
+        }
+        let mut count = 0;
+        let mut rest = mean;
+        while rest > 0.0 {
+            let chunk = rest.min(30.0);
+            rest -= chunk;
+            let limit = (-chunk).exp();
+            let mut product = self.next_f64();
+            while product > limit {
+                count += 1;
+                product *= self.next_f64();
+            }
+        }
+        count
+    }
+}
+
+impl Default for Rng {
+    /// A generator seeded from the clock.
+    fn default() -> Rng {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Rng::with_seed(now.as_secs() ^ u64::from(now.subsec_nanos()).rotate_left(32))
+    }
+}
+
+impl Calc {
+    pub fn builtin_seed(&mut self) -> Result<()> {
+        let seed = self.get_int()?;
+        let seed = seed.to_u64().or_else(|| seed.to_i64().map(|i| i as u64)).ok_or(ErrorKind::BigIntTooLarge)?;
+        self.seed(seed);
+        Ok(())
+    }
+
+    pub fn builtin_rand(&mut self) -> Result<()> {
+        let x = self.rng().next_f64();
+        self.data.push(Value::Float(x));
+        Ok(())
+    }
+
+    pub fn builtin_randint(&mut self) -> Result<()> {
+        let hi = self.get_int()?;
+        let lo = self.get_int()?;
+        if hi < lo {
+            bail!("empty range from {} to {}", lo, hi);
+        }
+        let span = (&hi - &lo + BigInt::from(1)).to_u64().ok_or(ErrorKind::BigIntTooLarge)?;
+        let offset = self.rng().below(span);
+        self.data.push(Value::Int(lo + BigInt::from(offset)));
+        Ok(())
+    }
+
+    pub fn builtin_shuffle(&mut self) -> Result<()> {
+        let mut vec = self.get_vector()?;
+        let mut rng = self.rng();
+        for i in (1..vec.len()).rev() {
+            let j = rng.below(i as u64 + 1) as usize;
+            vec.swap(i, j);
+        }
+        drop(rng);
+        self.data.push(Value::Vector(vec));
+        Ok(())
+    }
+
+    pub fn builtin_sample(&mut self) -> Result<()> {
+        let n = self.get_int()?;
+        let mut vec = self.get_vector()?;
+        let n = match n.to_usize() {
+            Some(n) if n <= vec.len() => n,
+            _ => return Err(ErrorKind::IndexOutOfRange(n.to_i64().unwrap_or(i64::max_value()), vec.len()).into()),
+        };
+        let mut rng = self.rng();
+        for i in 0..n {
+            let j = i + rng.below((vec.len() - i) as u64) as usize;
+            vec.swap(i, j);
+        }
+        drop(rng);
+        vec.truncate(n);
+        self.data.push(Value::Vector(vec));
+        Ok(())
+    }
+
+    pub fn builtin_choice(&mut self) -> Result<()> {
+        let vec = self.get_vector()?;
+        if vec.is_empty() {
+            return Err(ErrorKind::IndexOutOfRange(0, 0).into());
+        }
+        let i = self.rng().below(vec.len() as u64) as usize;
+        self.data.push(vec.into_iter().nth(i).unwrap());
+        Ok(())
+    }
+
+    pub fn builtin_normal(&mut self) -> Result<()> {
+        let sd = self.get_float_cast()?;
+        let mean = self.get_float_cast()?;
+        let x = mean + sd * self.rng().normal();
+        self.data.push(Value::Float(x));
+        Ok(())
+    }
+
+    pub fn builtin_exponential(&mut self) -> Result<()> {
+        let rate = self.get_float_cast()?;
+        if !(rate > 0.0) {
+            bail!("the rate of an exponential distribution must be positive, not {}", rate);
+        }
+        let x = -(1.0 - self.rng().next_f64()).ln() / rate;
+        self.data.push(Value::Float(x));
+        Ok(())
+    }
+
+    pub fn builtin_poisson(&mut self) -> Result<()> {
+        let mean = self.get_float_cast()?;
+        if !(mean >= 0.0) || mean > MAX_POISSON_MEAN {
+            bail!("the mean of a Poisson distribution must be a number from 0 to {}, not {}", MAX_POISSON_MEAN, mean);
+        }
+        let n = self.rng().poisson(mean);
+        self.data.push(Value::Int(BigInt::from(n)));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::{assert_errors, eval};
+
+    #[test]
+    fn test_rng() {
+        let mut a = Rng::with_seed(42);
+        let mut b = Rng::with_seed(42);
+        for _ in 0..100 {
+            assert_eq!(a.next_u64(), b.next_u64());
+            let x = a.next_f64();
+            assert!(x >= 0.0 && x < 1.0);
+            assert!(b.below(6) < 6);
+        }
+        let mean = (0..10000).map(|_| a.poisson(4.5) as f64).sum::<f64>() / 10000.0;
+        assert!((mean - 4.5).abs() < 0.1, "{}", mean);
+        let mean = (0..10000).map(|_| a.poisson(1e6) as f64).sum::<f64>() / 10000.0;
+        assert!((mean - 1e6).abs() < 100.0, "{}", mean);
+        let mean = (0..10000).map(|_| a.normal()).sum::<f64>() / 10000.0;
+        assert!(mean.abs() < 0.05, "{}", mean);
+    }
+
+    #[test]
+    fn test_words() {
+        let values = eval("7 seed rand 1 1000 randint 7 seed rand 1 1000 randint");
+        assert_eq!(values[..2], values[2..]);
+        assert_eq!(eval("[ 1 2 3 4 5 ] shuffle sort"), eval("[ 1 2 3 4 5 ]"));
+        assert_eq!(eval("5 upto shuffle sort"), eval("[ 1 2 3 4 5 ]"));
+        assert_eq!(eval("[ 1 2 3 4 5 ] 5 sample sort [ 1 2 3 ] 0 sample"), eval("[ 1 2 3 4 5 ] [ ]"));
+        assert_eq!(eval("[ 1 2 3 ] 2 sample len [ 9 ] choice 0 poisson"), eval("2 9 0"));
+        assert_eq!(eval("3 3 randint 5 0 normal"), eval("3 5.0"));
+        assert_errors(&["2 1 randint", "[ 1 ] 2 sample", "[ ] choice", "0 exponential", "-1 poisson", "1e19 poisson"]);
+        assert_eq!(eval("1e15 poisson").len(), 1);
+    }
+}
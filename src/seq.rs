@@ -0,0 +1,165 @@
+//! Lazy sequences, whose elements are computed as they are consumed.
+//!
+//! A sequence on the stack is never advanced itself: words consuming it
+//! iterate over a copy, so it can be used again, except for `lines` which
+//! reads from the one standard input.
+
+use std::io;
+use std::fmt;
+
+use num::{BigInt, One};
+
+use calc::Calc;
+use errors::*;
+use words::{Value, Word};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Seq {
+    /// The integers from `next` to `last`, inclusive.
+    Range { next: BigInt, last: BigInt },
+    /// `value`, the block applied to `value`, the block applied to that, ...
+    Iterate { value: Box<Value>, block: Vec<Word>, started: bool },
+    /// Lines of standard input, as numbers where possible.
+    Lines,
+    /// The remaining elements of a vector.
+    Items(Vec<Value>, usize),
+    Map(Box<Seq>, Vec<Word>),
+    Filter(Box<Seq>, Vec<Word>),
+    Take(Box<Seq>, usize),
+    Drop(Box<Seq>, usize),
+    TakeWhile(Box<Seq>, Vec<Word>),
+}
+
+impl Seq {
+    pub fn range(from: BigInt, to: BigInt) -> Seq {
+        Seq::Range { next: from, last: to }
+    }
+
+    pub fn iterate(value: Value, block: Vec<Word>) -> Seq {
+        Seq::Iterate { value: Box::new(value), block: block, started: false }
+    }
+
+    fn empty() -> Seq {
+        Seq::Items(Vec::new(), 0)
+    }
+
+    /// Compute the next element, running blocks in sub-calculators of `calc`.
+    pub fn next(&mut self, calc: &Calc) -> Result<Option<Value>> {
+        calc.tick()?;
+        let (value, done) = match *self {
+            Seq::Range { ref mut next, ref last } => {
+                if *next > *last {
+                    return Ok(None);
+                }
+                let value = next.clone();
+                *next = &*next + BigInt::one();
+                (Some(Value::Int(value)), false)
+            }
+            Seq::Iterate { ref mut value, ref block, ref mut started } => {
+                if *started {
+                    **value = calc.apply_block(block, vec![(**value).clone()])?;
+                }
+                *started = true;
+                (Some((**value).clone()), false)
+            }
+            Seq::Lines => {
+                let mut line = String::new();
+                if io::stdin().read_line(&mut line).chain_err(|| "could not read standard input")? == 0 {
+                    return Ok(None);
+                }
+                while line.ends_with('\n') || line.ends_with('\r') {
+                    line.pop();
+                }
+                (Some(Value::parse(&line).unwrap_or_else(|| Value::QuotedWord(line))), false)
+            }
+            Seq::Items(ref vec, ref mut i) => {
+                let value = vec.get(*i).cloned();
+                *i += 1;
+                (value, false)
+            }
+            Seq::Map(ref mut seq, ref block) => {
+                match seq.next(calc)? {
+                    Some(value) => (Some(calc.apply_block(block, vec![value])?), false),
+                    None => (None, false),
+                }
+            }
+            Seq::Filter(ref mut seq, ref block) => {
+                loop {
+                    match seq.next(calc)? {
+                        Some(value) => {
+                            if calc.apply_block(block, vec![value.clone()])?.is_true() {
+                                break (Some(value), false);
+                            }
+                        }
+                        None => break (None, false),
+                    }
+                }
+            }
+            Seq::Take(ref mut seq, ref mut n) => {
+                if *n == 0 {
+                    return Ok(None);
+                }
+                *n -= 1;
+                (seq.next(calc)?, false)
+            }
+            Seq::Drop(ref mut seq, ref mut n) => {
+                while *n > 0 {
+                    *n -= 1;
+                    if seq.next(calc)?.is_none() {
+                        return Ok(None);
+                    }
+                }
+                (seq.next(calc)?, false)
+            }
+            Seq::TakeWhile(ref mut seq, ref block) => {
+                match seq.next(calc)? {
+                    Some(value) => {
+                        if calc.apply_block(block, vec![value.clone()])?.is_true() {
+                            (Some(value), false)
+                        } else {
+                            (None, true)
+                        }
+                    }
+                    None => (None, false),
+                }
+            }
+        };
+        if done {
+            *self = Seq::empty();
+        }
+        Ok(value)
+    }
+
+    /// Whether the sequence is known to end, so that it can be collected
+    /// where a vector is expected. `lines` is not, as collecting it would
+    /// read all of standard input.
+    pub fn is_finite(&self) -> bool {
+        match *self {
+            Seq::Range { .. } | Seq::Items(..) | Seq::Take(..) => true,
+            Seq::Iterate { .. } | Seq::Lines => false,
+            Seq::Map(ref seq, _) | Seq::Filter(ref seq, _) | Seq::Drop(ref seq, _) | Seq::TakeWhile(ref seq, _) => {
+                seq.is_finite()
+            }
+        }
+    }
+
+    /// All the remaining elements. Does not return for an infinite sequence,
+    /// unless a limit is exceeded.
+    pub fn collect(mut self, calc: &Calc) -> Result<Vec<Value>> {
+        let mut vec = Vec::new();
+        while let Some(value) = self.next(calc)? {
+            vec.push(value);
+            calc.check_vector_len(vec.len())?;
+        }
+        Ok(vec)
+    }
+}
+
+impl fmt::Display for Seq {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Seq::Range { ref next, ref last } => write!(f, "<range {}..{}>", next, last),
+            _ => write!(f, "<seq>"),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Saving the state of a calculator as a pnc script which restores it when
+//! evaluated.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+use shlex;
+
+use dict::{Dictionary, Entry};
+use seq::Seq;
+use words::{Operation, Value, Word};
+
+/// Source code that pushes `value` on the stack.
+pub fn value_source(value: &Value) -> String {
+    match *value {
+        Value::Undef => "undef".to_owned(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(ref i) => i.to_string(),
+        // Debug formatting keeps the fraction so the value is read back as a float
+        Value::Float(f) => format!("{:?}", f),
+        Value::Rational(ref r) => r.to_string(),
+        Value::DateTime(t) => t.to_string(),
+        Value::Duration(d) => d.to_string(),
+        Value::Vector(ref vec) => {
+            let elems: Vec<String> = vec.iter().map(value_source).collect();
+            if elems.is_empty() {
+                "[ ]".to_owned()
+            } else {
+                format!("[ {} ]", elems.join(" "))
+            }
+        }
+        Value::Block(ref block) => {
+            let words: Vec<String> = block.iter().map(|w| shlex::quote(w).into_owned()).collect();
+            if words.is_empty() {
+                "{ }".to_owned()
+            } else {
+                format!("{{ {} }}", words.join(" "))
+            }
+        }
+        Value::QuotedWord(ref word) => quote_word(word),
+        Value::Error(ref kind, ref value) => format!("{} {} error", value_source(value), quote_word(kind)),
+        Value::Seq(ref seq) => seq_source(seq),
+    }
+}
+
+/// Source code creating `seq`. Sequences on the stack are never advanced, so
+/// rebuilding them from their definition restores them.
+fn seq_source(seq: &Seq) -> String {
+    let block = |block: &[Word]| value_source(&Value::Block(block.to_vec()));
+    match *seq {
+        Seq::Range { ref next, ref last } => format!("{} {} range", next, last),
+        Seq::Iterate { ref value, block: ref b, .. } => format!("{} {} iterate", value_source(value), block(b)),
+        Seq::Lines => "lines".to_owned(),
+        Seq::Items(ref vec, i) => value_source(&Value::Vector(vec[i..].to_vec())),
+        Seq::Map(ref seq, ref b) => format!("{} {} map", seq_source(seq), block(b)),
+        Seq::Filter(ref seq, ref b) => format!("{} {} filter", seq_source(seq), block(b)),
+        Seq::Take(ref seq, n) => format!("{} {} take", seq_source(seq), n),
+        Seq::Drop(ref seq, n) => format!("{} {} drop", seq_source(seq), n),
+        Seq::TakeWhile(ref seq, ref b) => format!("{} {} takewhile", seq_source(seq), block(b)),
+    }
+}
+
+fn quote_word(word: &str) -> String {
+    shlex::quote(&format!(",{}", word)).into_owned()
+}
+
+/// Write the alias `word` after the aliases it refers to, which must exist
+/// when it is defined.
+fn write_alias(out: &mut String, word: &str, aliases: &BTreeMap<Word, Word>, written: &mut HashSet<Word>) {
+    if !written.insert(word.to_owned()) {
+        return;
+    }
+    let other = &aliases[word];
+    if aliases.contains_key(other) {
+        write_alias(out, other, aliases, written);
+    }
+    writeln!(out, "{} {} alias", quote_word(word), quote_word(other)).unwrap();
+}
+
+/// A script recreating the entries defined directly in `dict` and the stack
+/// `data`. Native words cannot be saved and are left out. Definitions come
+/// before aliases, so the words aliases refer to exist.
+pub fn dump(dict: &Dictionary, data: &[Value]) -> String {
+    let mut out = String::new();
+    let mut aliases = BTreeMap::new();
+    for (word, entry) in dict.own_entries() {
+        match entry {
+            Entry::Op(op) => match *op {
+                Operation::Value(ref value) => {
+                    writeln!(out, "{} {} def", quote_word(&word), value_source(value)).unwrap();
+                }
+                Operation::Native(_) => {}
+            },
+            Entry::Alias(other) => {
+                aliases.insert(word, other);
+            }
+        }
+    }
+    let mut written = HashSet::new();
+    for word in aliases.keys() {
+        write_alias(&mut out, word, &aliases, &mut written);
+    }
+    for (word, doc) in dict.own_docs() {
+        let text = match doc.signature {
+            Some(signature) => format!("{} {}", signature, doc.text),
+            None => doc.text,
+        };
+        writeln!(out, "{} {} doc", quote_word(&word), quote_word(text.trim())).unwrap();
+    }
+    for value in data {
+        writeln!(out, "{}", value_source(value)).unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use calc::Calc;
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut calc = Calc::with_prelude().unwrap();
+        calc.eval(r#",sq { dup . } def ,square ,sq alias ,sq ",( x -- y ) Square of x." doc
+                     ,answer 42 def"#).unwrap();
+        calc.eval(r#"1 2.0 3.5 [ 1 [ 2 ] [ ] ] { 1 "+" } ",two words" { 0 div } { } try undef true -2/6
+                     3 upto { 2 . } map 1 { 1 + } iterate 5 take ,odd { 2 mod } def { odd } filter"#).unwrap();
+
+        let source = calc.session();
+        let mut restored = Calc::with_prelude().unwrap();
+        restored.eval(&source).unwrap();
+        assert_eq!(restored.data, calc.data);
+        assert_eq!(restored.session(), source);
+        restored.eval("3 square answer").unwrap();
+        assert_eq!(restored.data[restored.data.len() - 2..],
+                   [Value::parse("9").unwrap(), Value::parse("42").unwrap()]);
+    }
+
+    #[test]
+    fn test_alias_order() {
+        // The aliases sort before the words they refer to
+        let mut calc = Calc::with_prelude().unwrap();
+        calc.eval(",zz 5 def ,a ,zz alias ,b ,a alias ,aa ,b alias").unwrap();
+        let source = calc.session();
+        assert_eq!(source, ",zz 5 def\n,a ,zz alias\n,b ,a alias\n,aa ,b alias\n");
+
+        let mut restored = Calc::with_prelude().unwrap();
+        restored.eval(&source).unwrap();
+        restored.eval("aa").unwrap();
+        assert_eq!(restored.data, [Value::parse("5").unwrap()]);
+    }
+}
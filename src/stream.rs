@@ -0,0 +1,133 @@
+//! Running a block for each line of a stream, like awk.
+
+use std::io::BufRead;
+
+use shlex;
+
+use calc::Calc;
+use errors::*;
+use words::{Value, Word};
+
+/// Words of `source`, without the braces if it is written as a block.
+fn block_words(source: &str) -> Vec<Word> {
+    Calc::parse_block(source).unwrap_or_else(|| shlex::Shlex::new(source).collect())
+}
+
+/// The whitespace separated fields of a line. Fields which are not numbers
+/// become quoted words.
+pub fn fields(line: &str) -> Vec<Value> {
+    line.split_whitespace()
+        .map(|field| Value::parse(field).unwrap_or_else(|| Value::QuotedWord(field.to_owned())))
+        .collect()
+}
+
+pub struct Each {
+    each: Vec<Word>,
+    begin: Option<Vec<Word>>,
+    end: Option<Vec<Word>>,
+    vector: bool,
+}
+
+impl Each {
+    pub fn new(each: &str) -> Each {
+        Each {
+            each: block_words(each),
+            begin: None,
+            end: None,
+            vector: false,
+        }
+    }
+
+    pub fn begin(mut self, source: &str) -> Each {
+        self.begin = Some(block_words(source));
+        self
+    }
+
+    pub fn end(mut self, source: &str) -> Each {
+        self.end = Some(block_words(source));
+        self
+    }
+
+    /// Push the fields of a line as one vector instead of separate values.
+    pub fn vector(mut self, vector: bool) -> Each {
+        self.vector = vector;
+        self
+    }
+
+    /// Run the block for every line of `input`, one line at a time.
+    ///
+    /// Without begin and end blocks every line starts with an empty stack.
+    /// Otherwise the stack is kept between lines as an accumulator. `output`
+    /// is called after each line, or with an end block only once after it.
+    pub fn run<R, F>(&self, calc: &mut Calc, input: R, mut output: F) -> Result<()>
+        where R: BufRead,
+              F: FnMut(&Calc) -> Result<()>
+    {
+        let accumulate = self.begin.is_some() || self.end.is_some();
+        let per_line = self.end.is_none();
+        if let Some(ref begin) = self.begin {
+            calc.run(begin).chain_err(|| "failed to execute begin block")?;
+        }
+        for (n, line) in input.lines().enumerate() {
+            let line = line.chain_err(|| "could not read line")?;
+            if !accumulate {
+                calc.data.clear();
+            }
+            let fields = fields(&line);
+            if self.vector {
+                calc.data.push(Value::Vector(fields));
+            } else {
+                calc.data.extend(fields);
+            }
+            calc.run(&self.each).chain_err(|| format!("failed to execute block on line {}", n + 1))?;
+            if per_line {
+                output(calc)?;
+            }
+        }
+        if let Some(ref end) = self.end {
+            calc.run(end).chain_err(|| "failed to execute end block")?;
+            output(calc)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(each: &Each, input: &str) -> Vec<Vec<Value>> {
+        let mut calc = Calc::with_prelude().unwrap();
+        let mut stacks = vec![];
+        each.run(&mut calc, input.as_bytes(), |calc| {
+            stacks.push(calc.data.clone());
+            Ok(())
+        }).unwrap();
+        stacks
+    }
+
+    #[test]
+    fn test_each() {
+        let input = "1 2\n3 x\n\n";
+        assert_eq!(collect(&Each::new("{ len }").vector(true), input),
+                   vec![vec![Value::parse("2").unwrap()],
+                        vec![Value::parse("2").unwrap()],
+                        vec![Value::parse("0").unwrap()]]);
+        assert_eq!(collect(&Each::new("pop").begin("0").end("{ }"), "1 2\n3 4\n"),
+                   vec![vec![Value::parse("0").unwrap(), Value::parse("1").unwrap(), Value::parse("3").unwrap()]]);
+        assert_eq!(collect(&Each::new("{ + }").begin("0"), "1\n2\n3\n"),
+                   vec![vec![Value::parse("1").unwrap()],
+                        vec![Value::parse("3").unwrap()],
+                        vec![Value::parse("6").unwrap()]]);
+        assert_eq!(collect(&Each::new("{ + }").begin("0").end("{ }"), "1\n2\n3\n"),
+                   vec![vec![Value::parse("6").unwrap()]]);
+    }
+
+    #[test]
+    fn test_block_words() {
+        assert_eq!(block_words("{ 1 { 2 } }"), ["1", "{", "2", "}"]);
+        assert_eq!(block_words("{ 1 } { 2 }"), ["{", "1", "}", "{", "2", "}"]);
+        assert_eq!(block_words("dup +"), ["dup", "+"]);
+        assert_eq!(block_words("{ }"), Vec::<Word>::new());
+    }
+}
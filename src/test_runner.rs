@@ -0,0 +1,95 @@
+use std::fs::{self, File};
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use calc::Calc;
+use errors::*;
+
+/// Tally of the test words run so far.
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+/// Find all `*_test.pnc` files below `dir`, sorted by path.
+pub fn discover(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    let entries = fs::read_dir(dir)
+        .chain_err(|| format!("could not read directory {:?}", dir))?;
+    for entry in entries {
+        let path = entry.chain_err(|| format!("could not read directory {:?}", dir))?.path();
+        if path.is_dir() {
+            files.extend(discover(&path)?);
+        } else if path.file_name().and_then(|n| n.to_str()).map_or(false, |n| n.ends_with("_test.pnc")) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Run every `test-*` word defined by the test file at `path`.
+///
+/// Each test word runs in its own calculator obtained from `new_calc`, after
+/// the test file itself has been evaluated, so tests cannot affect each other.
+pub fn run_file<F>(path: &Path, new_calc: &F, summary: &mut Summary) -> Result<()>
+    where F: Fn() -> Result<Calc>
+{
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut f| f.read_to_string(&mut source))
+        .chain_err(|| format!("could not read test file {:?}", path))?;
+
+    let load = || -> Result<Calc> {
+        let mut calc = new_calc()?;
+        calc.eval(&source)
+            .chain_err(|| format!("failed to execute test file {:?}", path))?;
+        Ok(calc)
+    };
+
+    println!("{}", path.display());
+    let tests = load()?.defined_words().into_iter()
+        .filter(|word| word.starts_with("test-"));
+    for test in tests {
+        match load().and_then(|mut calc| calc.run_one(&test)) {
+            Ok(()) => {
+                summary.passed += 1;
+                println!("  {} ... ok", test);
+            }
+            Err(err) => {
+                summary.failed += 1;
+                println!("  {} ... FAILED: {}", test, err.root());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run all test files found below `dir` and report the results.
+pub fn run_dir<F>(dir: &Path, new_calc: &F) -> Result<Summary>
+    where F: Fn() -> Result<Calc>
+{
+    let mut summary = Summary::default();
+    for path in discover(dir)? {
+        run_file(&path, new_calc, &mut summary)?;
+    }
+    println!("{} passed, {} failed", summary.passed, summary.failed);
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use calc::Calc;
+    use super::*;
+
+    #[test]
+    fn test_prelude() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("prelude_test.pnc");
+        let mut summary = Summary::default();
+        run_file(&path, &Calc::with_prelude, &mut summary).unwrap();
+        assert!(summary.passed > 0);
+        assert_eq!(summary.failed, 0);
+    }
+}
@@ -0,0 +1,34 @@
+//! Helpers shared by the tests of the word modules.
+
+use calc::Calc;
+use words::Value;
+
+/// The stack left by `source` in a new calculator with the prelude loaded.
+pub fn eval(source: &str) -> Vec<Value> {
+    let mut calc = Calc::with_prelude().unwrap();
+    calc.eval(source).unwrap();
+    calc.data
+}
+
+/// The float left on top of the stack by `source`.
+pub fn eval_float(source: &str) -> f64 {
+    match eval(source).pop() {
+        Some(Value::Float(x)) => x,
+        v => panic!("{}: expected a float, got {:?}", source, v),
+    }
+}
+
+/// Check that each source leaves the same stack as the one after it.
+pub fn assert_cases(cases: &[(&str, &str)]) {
+    for &(source, expected) in cases {
+        assert_eq!(eval(source), eval(expected), "{}", source);
+    }
+}
+
+/// Check that each source fails.
+pub fn assert_errors(sources: &[&str]) {
+    for source in sources {
+        let mut calc = Calc::with_prelude().unwrap();
+        assert!(calc.eval(source).is_err(), "{}", source);
+    }
+}
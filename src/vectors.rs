@@ -0,0 +1,366 @@
+//! Builtin words for working with vectors.
+//!
+//! Indices start at 0, and negative indices count from the end of the vector.
+
+use std::cmp::Ordering;
+
+use num::{BigInt, ToPrimitive};
+
+use calc::Calc;
+use errors::*;
+use seq::Seq;
+use words::Value;
+
+/// Order of two values for sorting. Numbers compare by value, quoted words
+/// alphabetically and vectors element by element.
+pub fn compare(a: &Value, b: &Value) -> Result<Ordering> {
+    let ord = match (a, b) {
+        (&Value::Int(ref x), &Value::Int(ref y)) => Some(x.cmp(y)),
+        (&Value::QuotedWord(ref x), &Value::QuotedWord(ref y)) => Some(x.cmp(y)),
+        (&Value::Bool(x), &Value::Bool(y)) => Some(x.cmp(&y)),
+        (&Value::DateTime(x), &Value::DateTime(y)) => Some(x.cmp(&y)),
+        (&Value::Duration(x), &Value::Duration(y)) => Some(x.cmp(&y)),
+        (&Value::Undef, &Value::Undef) => Some(Ordering::Equal),
+        (&Value::Vector(ref x), &Value::Vector(ref y)) => {
+            for (a, b) in x.iter().zip(y) {
+                match compare(a, b)? {
+                    Ordering::Equal => {}
+                    ord => return Ok(ord),
+                }
+            }
+            Some(x.len().cmp(&y.len()))
+        }
+        (x, y) => match (x.as_rational(), y.as_rational()) {
+            (Some(x), Some(y)) => Some(x.cmp(&y)),
+            _ => match (x.as_float_cast(), y.as_float_cast()) {
+                (Some(x), Some(y)) => x.partial_cmp(&y),
+                _ => None,
+            },
+        },
+    };
+    ord.ok_or_else(|| {
+        format!("cannot compare '{}' (of type {}) with '{}' (of type {})", a, a.type_of(), b, b.type_of()).into()
+    })
+}
+
+/// The position of index `i` in a vector of length `len`.
+fn position(i: i64, len: usize) -> Result<usize> {
+    let pos = if i < 0 { len as i64 + i } else { i };
+    if pos < 0 || pos >= len as i64 {
+        return Err(ErrorKind::IndexOutOfRange(i, len).into());
+    }
+    Ok(pos as usize)
+}
+
+/// Like `position`, but clamped to `0..=len`, for slicing.
+fn bound(i: i64, len: usize) -> usize {
+    let pos = if i < 0 { len as i64 + i } else { i };
+    pos.max(0).min(len as i64) as usize
+}
+
+fn int(i: usize) -> Value {
+    Value::Int(BigInt::from(i))
+}
+
+fn boolean(b: bool) -> Value {
+    int(b as usize)
+}
+
+impl Calc {
+    fn get_index(&mut self) -> Result<i64> {
+        self.get_int()?.to_i64().ok_or_else(|| ErrorKind::BigIntTooLarge.into())
+    }
+
+    fn get_count(&mut self) -> Result<usize> {
+        self.get_int()?.to_usize().ok_or_else(|| ErrorKind::BigIntTooLarge.into())
+    }
+
+    pub fn builtin_nth(&mut self) -> Result<()> {
+        let i = self.get_index()?;
+        let vec = self.get_vector()?;
+        let pos = position(i, vec.len())?;
+        self.data.push(vec.into_iter().nth(pos).unwrap());
+        Ok(())
+    }
+
+    pub fn builtin_slice(&mut self) -> Result<()> {
+        let to = self.get_index()?;
+        let from = self.get_index()?;
+        let vec = self.get_vector()?;
+        let (from, to) = (bound(from, vec.len()), bound(to, vec.len()));
+        let slice = if from < to { vec[from..to].to_vec() } else { vec![] };
+        self.data.push(Value::Vector(slice));
+        Ok(())
+    }
+
+    pub fn builtin_reverse(&mut self) -> Result<()> {
+        let mut vec = self.get_vector()?;
+        vec.reverse();
+        self.data.push(Value::Vector(vec));
+        Ok(())
+    }
+
+    /// Sort `vec` by `keys`, stably. Returns the first comparison error.
+    fn sort_by_keys(vec: Vec<Value>, keys: Vec<Value>) -> Result<Vec<Value>> {
+        let mut error = None;
+        let mut pairs: Vec<_> = keys.into_iter().zip(vec).collect();
+        pairs.sort_by(|a, b| {
+            compare(&a.0, &b.0).unwrap_or_else(|e| {
+                error = error.take().or(Some(e));
+                Ordering::Equal
+            })
+        });
+        match error {
+            Some(e) => Err(e),
+            None => Ok(pairs.into_iter().map(|(_, v)| v).collect()),
+        }
+    }
+
+    pub fn builtin_sort(&mut self) -> Result<()> {
+        let vec = self.get_vector()?;
+        let sorted = Calc::sort_by_keys(vec.clone(), vec)?;
+        self.data.push(Value::Vector(sorted));
+        Ok(())
+    }
+
+    pub fn builtin_sortby(&mut self) -> Result<()> {
+        let block = self.get_block()?;
+        let vec = self.get_vector()?;
+        let mut keys = Vec::with_capacity(vec.len());
+        for val in &vec {
+            keys.push(self.apply_block(&block, vec![val.clone()])?);
+        }
+        let sorted = Calc::sort_by_keys(vec, keys)?;
+        self.data.push(Value::Vector(sorted));
+        Ok(())
+    }
+
+    pub fn builtin_uniq(&mut self) -> Result<()> {
+        let vec = self.get_vector()?;
+        let mut result: Vec<Value> = Vec::new();
+        for val in vec {
+            if !result.contains(&val) {
+                result.push(val);
+            }
+        }
+        self.data.push(Value::Vector(result));
+        Ok(())
+    }
+
+    pub fn builtin_zip(&mut self) -> Result<()> {
+        let ys = self.get_vector()?;
+        let xs = self.get_vector()?;
+        let pairs = xs.into_iter().zip(ys).map(|(x, y)| Value::Vector(vec![x, y])).collect();
+        self.data.push(Value::Vector(pairs));
+        Ok(())
+    }
+
+    pub fn builtin_concat(&mut self) -> Result<()> {
+        let ys = self.get_vector()?;
+        let mut xs = self.get_vector()?;
+        xs.extend(ys);
+        self.check_vector_len(xs.len())?;
+        self.data.push(Value::Vector(xs));
+        Ok(())
+    }
+
+    pub fn builtin_flatten(&mut self) -> Result<()> {
+        let vec = self.get_vector()?;
+        let mut result = Vec::with_capacity(vec.len());
+        for val in vec {
+            match val {
+                Value::Vector(inner) => result.extend(inner),
+                val => result.push(val),
+            }
+        }
+        self.check_vector_len(result.len())?;
+        self.data.push(Value::Vector(result));
+        Ok(())
+    }
+
+    pub fn builtin_push(&mut self) -> Result<()> {
+        let val = self.get_operand()?;
+        let mut vec = self.get_vector()?;
+        vec.push(val);
+        self.data.push(Value::Vector(vec));
+        Ok(())
+    }
+
+    pub fn builtin_pop_back(&mut self) -> Result<()> {
+        let mut vec = self.get_vector()?;
+        let val = vec.pop().ok_or::<Error>(ErrorKind::IndexOutOfRange(-1, 0).into())?;
+        self.data.push(Value::Vector(vec));
+        self.data.push(val);
+        Ok(())
+    }
+
+    pub fn builtin_first(&mut self) -> Result<()> {
+        let val = self.get_seq()?.next(self)?
+            .ok_or::<Error>(ErrorKind::IndexOutOfRange(0, 0).into())?;
+        self.data.push(val);
+        Ok(())
+    }
+
+    pub fn builtin_last(&mut self) -> Result<()> {
+        let mut vec = self.get_vector()?;
+        let val = vec.pop().ok_or::<Error>(ErrorKind::IndexOutOfRange(-1, 0).into())?;
+        self.data.push(val);
+        Ok(())
+    }
+
+    pub fn builtin_drop(&mut self) -> Result<()> {
+        let n = self.get_count()?;
+        match self.get_operand()? {
+            Value::Vector(vec) => {
+                let rest = vec.into_iter().skip(n).collect();
+                self.data.push(Value::Vector(rest));
+            }
+            Value::Seq(seq) => self.data.push(Value::Seq(Seq::Drop(Box::new(seq), n))),
+            v => return Err(ErrorKind::WrongTypeOperand(v, "vector or seq").into()),
+        }
+        Ok(())
+    }
+
+    pub fn builtin_find(&mut self) -> Result<()> {
+        let block = self.get_block()?;
+        let mut seq = self.get_seq()?;
+        while let Some(val) = seq.next(self)? {
+            if self.apply_block(&block, vec![val.clone()])?.is_true() {
+                self.data.push(val);
+                return Ok(());
+            }
+        }
+        self.data.push(Value::Undef);
+        Ok(())
+    }
+
+    pub fn builtin_indexof(&mut self) -> Result<()> {
+        let x = self.get_operand()?;
+        let mut seq = self.get_seq()?;
+        let mut i = 0;
+        while let Some(val) = seq.next(self)? {
+            if val == x {
+                self.data.push(int(i));
+                return Ok(());
+            }
+            i += 1;
+        }
+        self.data.push(Value::Int(BigInt::from(-1)));
+        Ok(())
+    }
+
+    /// Count the elements of a sequence for which `block` is true, stopping
+    /// early once `stop` says the result is known.
+    fn count_true<F>(&mut self, mut stop: F) -> Result<usize>
+        where F: FnMut(bool) -> bool
+    {
+        let block = self.get_block()?;
+        let mut seq = self.get_seq()?;
+        let mut count = 0;
+        while let Some(val) = seq.next(self)? {
+            let test = self.apply_block(&block, vec![val])?.is_true();
+            if test {
+                count += 1;
+            }
+            if stop(test) {
+                break;
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn builtin_any(&mut self) -> Result<()> {
+        let count = self.count_true(|test| test)?;
+        self.data.push(boolean(count > 0));
+        Ok(())
+    }
+
+    pub fn builtin_all(&mut self) -> Result<()> {
+        let mut failed = false;
+        self.count_true(|test| {
+            failed = !test;
+            failed
+        })?;
+        self.data.push(boolean(!failed));
+        Ok(())
+    }
+
+    pub fn builtin_count(&mut self) -> Result<()> {
+        let count = self.count_true(|_| false)?;
+        self.data.push(int(count));
+        Ok(())
+    }
+
+    pub fn builtin_group(&mut self) -> Result<()> {
+        let block = self.get_block()?;
+        let vec = self.get_vector()?;
+        let mut groups: Vec<(Value, Vec<Value>)> = Vec::new();
+        for val in vec {
+            let key = self.apply_block(&block, vec![val.clone()])?;
+            match groups.iter().position(|&(ref k, _)| *k == key) {
+                Some(i) => groups[i].1.push(val),
+                None => groups.push((key, vec![val])),
+            }
+        }
+        let groups = groups.into_iter()
+            .map(|(key, vals)| Value::Vector(vec![key, Value::Vector(vals)]))
+            .collect();
+        self.data.push(Value::Vector(groups));
+        Ok(())
+    }
+
+    pub fn builtin_explode(&mut self) -> Result<()> {
+        let vec = self.get_vector()?;
+        self.data.extend(vec);
+        Ok(())
+    }
+
+    pub fn builtin_pack(&mut self) -> Result<()> {
+        let n = self.get_count()?;
+        if n > self.data.len() {
+            return Err(ErrorKind::MissingOperand.into());
+        }
+        let start = self.data.len() - n;
+        let vec = self.data.split_off(start);
+        self.data.push(Value::Vector(vec));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use testing::{assert_cases, assert_errors};
+
+    #[test]
+    fn test_vectors() {
+        assert_cases(&[
+            ("[ 5 6 7 ] 1 nth", "6"),
+            ("[ 5 6 7 ] -1 nth", "7"),
+            ("[ 1 2 3 4 5 ] 1 -1 slice", "[ 2 3 4 ]"),
+            ("[ 1 2 3 ] reverse", "[ 3 2 1 ]"),
+            ("[ 3 1.5 2 ] sort", "[ 1.5 2 3 ]"),
+            ("[ ,b ,a ,c ] sort", "[ ,a ,b ,c ]"),
+            ("[ [ 1 ,x ] [ 0 ,y ] ] { 0 nth } sortby", "[ [ 0 ,y ] [ 1 ,x ] ]"),
+            ("[ 1 2 1 3 2 ] uniq", "[ 1 2 3 ]"),
+            ("[ 1 2 3 ] [ ,a ,b ] zip", "[ [ 1 ,a ] [ 2 ,b ] ]"),
+            ("[ 1 ] [ 2 3 ] concat", "[ 1 2 3 ]"),
+            ("[ [ 1 2 ] 3 [ [ 4 ] ] ] flatten", "[ 1 2 3 [ 4 ] ]"),
+            ("[ 1 ] 2 push pop-back", "[ 1 ] 2"),
+            ("[ 4 5 ] first [ 4 5 ] last", "4 5"),
+            ("[ 1 2 3 ] 1 drop 10 upto 8 drop collect", "[ 2 3 ] [ 9 10 ]"),
+            ("10 upto { 3 gt } find [ 1 2 ] { 5 gt } find", "4 undef"),
+            ("[ 4 5 6 ] 6 indexof [ 4 ] 7 indexof", "2 -1"),
+            ("[ 1 2 3 ] { 2 gt } any [ 1 2 3 ] { 2 gt } all [ ] { 2 gt } all", "1 0 1"),
+            ("10 upto { 2 mod } count", "5"),
+            ("10 upto 3 nth 3 upto reverse 3 upto { 2 . } map last", "4 [ 3 2 1 ] 6"),
+            ("[ 1 2 3 4 ] { 2 mod } group", "[ [ 1 [ 1 3 ] ] [ 0 [ 2 4 ] ] ]"),
+            ("[ 1 2 3 ] explode + 2 pack", "[ 1 5 ]"),
+        ]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_errors(&["[ 1 2 ] 2 nth", "[ ] first", "[ ] pop-back", "[ 1 ,a ] sort", "1 2 3 pack",
+                        "1 { 1 + } iterate len"]);
+    }
+}
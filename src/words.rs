@@ -1,73 +1,78 @@
 use std::fmt;
+use std::rc::Rc;
 
 use num::bigint::BigInt;
-use num::ToPrimitive;
+use num::rational::BigRational;
+use num::{ToPrimitive, Zero};
+
+use calc::Calc;
+use errors;
+use datetime::{DateTime, Duration};
+use seq::Seq;
 
 pub type Word = String;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum BuiltinWord {
-    // Arithmetic
-    Add,
-    Sub,
-    Mul,
-    Div,
-    Mod,
-    Sqrt,
-    Pow,
-    Exp,
-    Log,
-    Ln,
-    Sin,
-    Cos,
-    Tan,
-    Asin,
-    Acos,
-    Atan,
-
-    // Functional
-    Fold,
-    Fold1,
-    Map,
-    Filter,
-    Repeat,
-
-    // Comparisons
-    Max,
-    Min,
-    Cmp,
-
-    // Blocks
-    Alias,
-    Def,
-    Apply,
-    Arg,
-
-    // Stack manipulation
-    Swap,
-    Duplicate,
-    Pop,
-    Over,
-    Roll3,
-
-    // IO
-    Print,
-    Dump,
-    Stdin,
-
-    // Vectors
-    Length,
-
-    // Control flow
-    If,
+/// A word implemented in Rust.
+#[derive(Clone)]
+pub struct Native {
+    pub name: Word,
+    pub func: Rc<dyn Fn(&mut Calc) -> errors::Result<()>>,
+    /// Whether the word does input or output, and so is disabled in sandbox mode.
+    pub io: bool,
+}
+
+impl Native {
+    pub fn new<F>(name: &str, f: F) -> Native
+        where F: Fn(&mut Calc) -> errors::Result<()> + 'static
+    {
+        Native {
+            name: name.to_owned(),
+            func: Rc::new(f),
+            io: false,
+        }
+    }
+}
+
+thread_local! {
+    static ARG: Native = Native::new("arg", |_| Ok(()));
+}
+
+impl Native {
+    /// The builtin `arg`, which is not executed but recognised by the parser
+    /// of vectors.
+    pub fn arg() -> Native {
+        ARG.with(Native::clone)
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Native({:?})", self.name)
+    }
+}
+
+impl PartialEq for Native {
+    fn eq(&self, other: &Native) -> bool {
+        Rc::ptr_eq(&self.func, &other.func)
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Operation {
-    Builtin(BuiltinWord),
+    Native(Native),
     Value(Value),
 }
 
+impl Operation {
+    /// Whether this is the builtin `arg`, under whatever name it is used.
+    pub fn is_arg(&self) -> bool {
+        match *self {
+            Operation::Native(ref native) => ARG.with(|arg| native == arg),
+            _ => false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
     Undef,
@@ -75,9 +80,15 @@ pub enum Value {
     Bool(bool),
     Int(BigInt),
     Float(f64),
+    /// A fraction which is not an integer, in lowest terms.
+    Rational(BigRational),
     Vector(Vec<Value>),
     Block(Vec<Word>),
     QuotedWord(Word),
+    Error(Word, Box<Value>),
+    Seq(Seq),
+    DateTime(DateTime),
+    Duration(Duration),
 }
 
 impl Value {
@@ -87,11 +98,40 @@ impl Value {
             Some(Value::Int(num))
         } else if let Ok(num) = s.parse::<f64>() {
             Some(Value::Float(num))
+        } else if let Some(slash) = s.find('/') {
+            let numer = BigInt::parse_bytes(s[..slash].as_bytes(), 10)?;
+            let denom = BigInt::parse_bytes(s[slash + 1..].as_bytes(), 10)?;
+            if denom.is_zero() {
+                return None;
+            }
+            Some(Value::from_rational(BigRational::new(numer, denom)))
+        } else if let Some(t) = DateTime::parse(s) {
+            Some(Value::DateTime(t))
+        } else if let Some(d) = Duration::parse(s) {
+            Some(Value::Duration(d))
         } else {
             None
         }
     }
 
+    /// An int if `r` is an integer, otherwise a rational.
+    pub fn from_rational(r: BigRational) -> Value {
+        if r.is_integer() {
+            Value::Int(r.to_integer())
+        } else {
+            Value::Rational(r)
+        }
+    }
+
+    /// The value of an int or rational as an exact fraction.
+    pub fn as_rational(&self) -> Option<BigRational> {
+        match *self {
+            Value::Int(ref i) => Some(BigRational::from_integer(i.clone())),
+            Value::Rational(ref r) => Some(r.clone()),
+            _ => None,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn as_bool(&self) -> Option<bool> {
         match *self {
@@ -100,6 +140,17 @@ impl Value {
         }
     }
 
+    /// Whether the value counts as true for filter and assert.
+    pub fn is_true(&self) -> bool {
+        match *self {
+            Value::Int(ref x) => !x.is_zero(),
+            Value::Bool(b) => b,
+            Value::Rational(ref r) => !r.is_zero(),
+            Value::Float(x) => x != 0.0,
+            _ => false,
+        }
+    }
+
     pub fn as_int(&self) -> Option<BigInt> {
         match *self {
             Value::Int(ref i) => Some(i.clone()),
@@ -133,6 +184,7 @@ impl Value {
         match *self {
             Value::Float(f) => Some(f),
             Value::Int(ref i) => i.to_f64(),
+            Value::Rational(ref r) => Some(r.numer().to_f64()? / r.denom().to_f64()?),
             _ => None,
         }
     }
@@ -157,9 +209,14 @@ impl Value {
             Value::Bool(..)       => "type",
             Value::Int(..)        => "int",
             Value::Float(..)      => "float",
+            Value::Rational(..)   => "rational",
             Value::Vector(..)     => "vector",
             Value::Block(..)      => "block",
             Value::QuotedWord(..) => "quoted word",
+            Value::Error(..)      => "error",
+            Value::Seq(..)        => "seq",
+            Value::DateTime(..)   => "date",
+            Value::Duration(..)   => "duration",
         }
     }
 }
@@ -172,6 +229,7 @@ impl fmt::Display for Value {
             Bool(v) => v.fmt(f),
             Int(ref v) => write!(f, "{}", v),
             Float(v) => write!(f, "{}", v),
+            Rational(ref v) => write!(f, "{}", v),
             Vector(ref v) => {
                 write!(f, "[")?;
                 let mut iter = v.into_iter();
@@ -188,6 +246,10 @@ impl fmt::Display for Value {
             }
             Block(_) => write!(f, "<block>"),
             QuotedWord(ref word) => write!(f, "{}", word),
+            Error(ref kind, ref value) => write!(f, "<error {}: {}>", kind, value),
+            Seq(ref seq) => seq.fmt(f),
+            DateTime(ref t) => t.fmt(f),
+            Duration(ref d) => d.fmt(f),
         }
     }
 }